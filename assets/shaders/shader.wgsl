@@ -3,7 +3,11 @@ struct ShaderData {
     player_direction: vec2<f32>,
     goal_position: vec2<f32>,
     size_info: vec2<f32>,
+    sonar: vec2<f32>,
     color: vec4<f32>,
+    lights: array<vec4<f32>, 16>,
+    light_colors: array<vec4<f32>, 16>,
+    light_count: u32,
 }
 
 @group(1) @binding(0)
@@ -30,12 +34,23 @@ fn fragment(in: FragmentInput
     // Correct for the 0.5 offset
     uv_world -= vec2<f32>(0.5, 0.5);
 
+    // Level lights reveal the world around them, tinted by their colour
+    for (var i = 0u; i < data.light_count; i++) {
+        let light = data.lights[i];
+        let light_factor = clamp(1.0 - distance(light.xy, uv_world) / light.z, 0.0, 1.0) * light.w;
+        final_color = mix(final_color, world_color * data.light_colors[i], light_factor);
+    }
+
     let player_distance = distance(data.player_position, uv_world);
     let goal_distance = distance(data.goal_position, uv_world);
     // Draw a circle with a radius of 2.0 around player and goal
     if (player_distance <= 1.5 || goal_distance <= 1.5) {
         return world_color;
     }
+    // The sonar shows the walls around the player whatever the direction
+    if (player_distance <= data.sonar.x) {
+        final_color = mix(final_color, world_color, data.sonar.y);
+    }
     if (player_distance >= 6.0) {
         return final_color;
     }
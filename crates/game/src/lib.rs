@@ -2,7 +2,11 @@ pub mod map;
 pub mod plugins;
 
 pub use bevy::{prelude::*, diagnostic::{LogDiagnosticsPlugin, FrameTimeDiagnosticsPlugin}};
-pub use map::parser::{parse_map, MapSource, MapFile};
+pub use map::parser::{parse_map, validate_map, MapSource, MapFile};
+pub use map::code::{encode_map, decode_map};
+pub use map::ldtk::{export_ldtk, write_ldtk};
+pub use map::png::{import_png, PngSource, PngLayout, PngLegend, LegendEntry, LegendItem};
 pub use plugins::plugin::StatePlugin;
 pub use map::map_manager::MapManager;
+pub use map::progress::Progress;
 pub use std::{env, path::PathBuf};
@@ -0,0 +1,113 @@
+use std::error::Error;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bincode::Options;
+use map_shared::MapData;
+
+// Bump this when the binary layout of MapData changes so old codes are rejected cleanly
+const CODE_VERSION: u8 = 2;
+// Upper bound for a decoded map, protects against corrupted or malicious codes
+const MAX_MAP_BYTES: usize = 1024 * 1024;
+
+/*
+    A map code is: base64url( version | deflate(bincode(MapData)) | crc32 )
+    The checksum covers the version byte and the compressed data
+*/
+pub fn encode_map(map_data: &MapData) -> Result<String, Box<dyn Error>> {
+    let binary = bincode_options().serialize(map_data)?;
+    let compressed = miniz_oxide::deflate::compress_to_vec(&binary, 10);
+
+    let mut payload = Vec::with_capacity(compressed.len() + 5);
+    payload.push(CODE_VERSION);
+    payload.extend_from_slice(&compressed);
+    let checksum = crc32fast::hash(&payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+
+    Ok(URL_SAFE_NO_PAD.encode(payload))
+}
+
+pub fn decode_map(code: &str) -> Result<MapData, Box<dyn Error>> {
+    // Chat clients like to wrap long lines, ignore any whitespace
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let payload = URL_SAFE_NO_PAD.decode(code)?;
+    if payload.len() < 5 {
+        return Err("Map code is too short".into());
+    }
+
+    let (body, checksum) = payload.split_at(payload.len() - 4);
+    if crc32fast::hash(body).to_le_bytes() != checksum {
+        return Err("Map code checksum does not match, it was probably not copied entirely".into());
+    }
+    if body[0] != CODE_VERSION {
+        return Err(format!("Unsupported map code version {}", body[0]).into());
+    }
+
+    let binary = miniz_oxide::inflate::decompress_to_vec_with_limit(&body[1..], MAX_MAP_BYTES)
+        .map_err(|_| "Map code does not contain valid compressed data")?;
+    let data = bincode_options().deserialize(&binary)?;
+    Ok(data)
+}
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_MAP_BYTES as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_map, MapFile, MapSource};
+
+    fn tuto() -> MapData {
+        parse_map(MapSource::FileContent(MapFile::Tuto)).unwrap()
+    }
+
+    // Raw payload of a code, without the base64
+    fn payload(map_data: &MapData) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(encode_map(map_data).unwrap()).unwrap()
+    }
+
+    fn with_checksum(mut body: Vec<u8>) -> String {
+        let checksum = crc32fast::hash(&body);
+        body.extend_from_slice(&checksum.to_le_bytes());
+        URL_SAFE_NO_PAD.encode(body)
+    }
+
+    fn decode_error(code: &str) -> String {
+        decode_map(code).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn decoded_map_matches_the_encoded_one() {
+        let map_data = tuto();
+        let decoded = decode_map(&encode_map(&map_data).unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&map_data).unwrap());
+    }
+
+    #[test]
+    fn corrupted_checksum_is_rejected() {
+        let mut payload = payload(&tuto());
+        *payload.last_mut().unwrap() ^= 0xff;
+        assert!(decode_error(&URL_SAFE_NO_PAD.encode(payload)).contains("checksum does not match"));
+    }
+
+    #[test]
+    fn other_version_is_rejected() {
+        let mut payload = payload(&tuto());
+        payload.truncate(payload.len() - 4);
+        payload[0] = CODE_VERSION - 1;
+        assert_eq!(decode_error(&with_checksum(payload)), format!("Unsupported map code version {}", CODE_VERSION - 1));
+    }
+
+    #[test]
+    fn truncated_code_is_rejected() {
+        // Cut on a base64 block so only the missing end is noticed
+        let code = encode_map(&tuto()).unwrap();
+        assert!(decode_error(&code[..code.len() / 8 * 4]).contains("checksum does not match"));
+        assert_eq!(decode_error(&code[..4]), "Map code is too short");
+
+        // A cut body with a valid checksum still fails on the compressed data
+        let mut payload = payload(&tuto());
+        payload.truncate(payload.len() / 2);
+        assert_eq!(decode_error(&with_checksum(payload)), "Map code does not contain valid compressed data");
+    }
+}
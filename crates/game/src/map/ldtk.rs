@@ -0,0 +1,388 @@
+use std::{error::Error, fs, path::Path};
+
+use map_shared::{Dimension, MapData, TerrainKind, ZoneKind};
+use serde_json::{json, Value};
+
+// The exported project reuses the layout of our own LDtk project
+const TEMPLATE: &str = include_str!("../../../../assets/levels/TheVeiledPath.ldtk");
+const GRID_SIZE: i32 = 16;
+const WALL_VALUE: i32 = 1;
+
+pub fn write_ldtk(map_data: &MapData, path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::write(path, export_ldtk(map_data)?)?;
+    Ok(())
+}
+
+pub fn export_ldtk(map_data: &MapData) -> Result<String, Box<dyn Error>> {
+    let mut project: Value = serde_json::from_str(TEMPLATE)?;
+    let mut ids = IdGenerator::new(project["nextUid"].as_i64().ok_or("LDtk template has no nextUid")?);
+    let width = map_data.size;
+    let height = (map_data.size / 16) * 9;
+
+    // Layer definitions, one IntGrid layer per dimension first as in the template and the entity layer after them
+    let layer_defs = project["defs"]["layers"].as_array_mut().ok_or("LDtk template has no layers")?;
+    let dimension_layer_def = layer_defs.first().ok_or("LDtk template has no layers")?.clone();
+    let mut dimension_layer_uids = Vec::new();
+    layer_defs.clear();
+    for index in 0..map_data.dimensions.len() {
+        let uid = ids.uid();
+        let mut layer_def = dimension_layer_def.clone();
+        layer_def["identifier"] = json!(dimension_layer_name(index));
+        layer_def["uid"] = json!(uid);
+        layer_def["intGridValues"][0]["identifier"] = json!("Wall");
+        layer_defs.push(layer_def);
+        dimension_layer_uids.push(uid);
+    }
+    let entity_layer_uid = ids.uid();
+    layer_defs.push(entity_layer_def(entity_layer_uid));
+
+    // Entity definitions
+    // A timer of 0 keeps the door open, keys_needed, generic and consume are used by the inventory key rule
+    let door = EntityDef::new(
+        &mut ids, "Door", "#BE4A2F", &[("id", "Int"), ("timer", "Float"), ("keys_needed", "Int"), ("generic", "Bool"), ("consume", "Bool")],
+    );
+    let key = EntityDef::new(&mut ids, "Key", "#FEAE34", &[("door_id", "Int"), ("generic", "Bool")]);
+    let start = EntityDef::global(&mut ids, "Start", "#63C74D", &[]);
+    let goal = EntityDef::global(&mut ids, "Goal", "#2CE8F5", &[]);
+    let checkpoint = EntityDef::new(&mut ids, "Checkpoint", "#B86F50", &[]);
+    let collectible = EntityDef::new(&mut ids, "Collectible", "#8FF3E6", &[]);
+    // flicker goes from 0, steady, to 1
+    let light = EntityDef::new(&mut ids, "Light", "#FFE4A1", &[("radius", "Float"), ("color", "Color"), ("flicker", "Float")]);
+    let block = EntityDef::new(&mut ids, "Block", "#733E39", &[]);
+    let sign = EntityDef::new(&mut ids, "Sign", "#E4A672", &[("text", "String"), ("radius", "Float")]);
+    // kind is "Spikes" or "Laser", it is active for active seconds every period, starting at phase
+    let hazard = EntityDef::new(
+        &mut ids, "Hazard", "#A22633", &[("kind", "String"), ("period", "Float"), ("active", "Float"), ("phase", "Float")],
+    );
+    let energy_pickup = EntityDef::new(&mut ids, "EnergyPickup", "#FEE761", &[("amount", "Float")]);
+    // kind is "Block", "Force" or "Flip", target is only used by Force and period by Flip
+    let switch_zone = EntityDef::global(
+        &mut ids, "SwitchZone", "#68386C", &[("width", "Int"), ("height", "Int"), ("kind", "String"), ("target", "String"), ("period", "Float")],
+    );
+    // An empty exit_dimension keeps the player in its current dimension
+    let portal = EntityDef::new(&mut ids, "Portal", "#B55088", &[("id", "Int"), ("exit_id", "Int"), ("exit_dimension", "String")]);
+    let gate = EntityDef::new(&mut ids, "Gate", "#5A6988", &[("group", "Int"), ("open", "Bool")]);
+    // kind is "Lever" or "Plate"
+    let switch = EntityDef::new(&mut ids, "Switch", "#F77622", &[("group", "Int"), ("kind", "String")]);
+    // path is written as "x,y x,y ...", mode is "PingPong" or "Loop"
+    let moving_wall = EntityDef::new(&mut ids, "MovingWall", "#8B9BB4", &[("path", "String"), ("speed", "Float"), ("mode", "String")]);
+    let sentry = EntityDef::new(&mut ids, "Sentry", "#E43B44", &[("path", "String"), ("speed", "Float"), ("mode", "String"), ("chase_radius", "Float")]);
+    // direction is "Up", "Down", "Left" or "Right", kind is "Arrow" or "Door"
+    let one_way = EntityDef::new(&mut ids, "OneWay", "#3E8948", &[("direction", "String"), ("kind", "String")]);
+    // kind is "Ice", "Mud" or "Conveyor", direction is only used by conveyors
+    let terrain = EntityDef::new(&mut ids, "Terrain", "#C0CBDC", &[("kind", "String"), ("direction", "String")]);
+    let entity_defs = project["defs"]["entities"].as_array_mut().ok_or("LDtk template has no entities")?;
+    for entity_def in [&door, &key, &start, &goal, &portal, &gate, &switch, &moving_wall, &sentry, &one_way, &terrain, &checkpoint, &collectible, &energy_pickup, &switch_zone, &light, &block, &hazard, &sign] {
+        entity_defs.push(entity_def.to_json());
+    }
+
+    // Entities
+    let mut entities = Vec::new();
+    entities.push(start.instance(&mut ids, map_data.start_x, map_data.start_y, Vec::new()));
+    entities.push(goal.instance(&mut ids, map_data.goal_x, map_data.goal_y, Vec::new()));
+    for d in &map_data.doors {
+        let fields = vec![
+            json!(d.id), json!(d.timer), json!(d.keys_needed), json!(d.generic), json!(d.consume), json!(d.dimension.to_string()),
+        ];
+        entities.push(door.instance(&mut ids, d.x, d.y, fields));
+    }
+    for k in &map_data.keys {
+        let fields = vec![json!(k.door_id), json!(k.generic), json!(k.dimension.to_string())];
+        entities.push(key.instance(&mut ids, k.x, k.y, fields));
+    }
+    for p in &map_data.portals {
+        let exit_dimension = p.exit_dimension.map(|d| d.to_string()).unwrap_or_default();
+        let fields = vec![json!(p.id), json!(p.exit_id), json!(exit_dimension), json!(p.dimension.to_string())];
+        entities.push(portal.instance(&mut ids, p.x, p.y, fields));
+    }
+    for g in &map_data.gates {
+        let fields = vec![json!(g.group), json!(g.open), json!(g.dimension.to_string())];
+        entities.push(gate.instance(&mut ids, g.x, g.y, fields));
+    }
+    for s in &map_data.switches {
+        let fields = vec![json!(s.group), json!(format!("{:?}", s.kind)), json!(s.dimension.to_string())];
+        entities.push(switch.instance(&mut ids, s.x, s.y, fields));
+    }
+    for m in &map_data.moving_walls {
+        let Some([x, y]) = m.path.first() else { continue };
+        let fields = vec![json!(path_field(&m.path)), json!(m.speed), json!(format!("{:?}", m.mode)), json!(m.dimension.to_string())];
+        entities.push(moving_wall.instance(&mut ids, *x, *y, fields));
+    }
+    for s in &map_data.sentries {
+        let Some([x, y]) = s.path.first() else { continue };
+        let fields = vec![
+            json!(path_field(&s.path)), json!(s.speed), json!(format!("{:?}", s.mode)), json!(s.chase_radius), json!(s.dimension.to_string()),
+        ];
+        entities.push(sentry.instance(&mut ids, *x, *y, fields));
+    }
+    for o in &map_data.one_ways {
+        let fields = vec![json!(format!("{:?}", o.direction)), json!(format!("{:?}", o.kind)), json!(o.dimension.to_string())];
+        entities.push(one_way.instance(&mut ids, o.x, o.y, fields));
+    }
+    for t in &map_data.terrains {
+        let (kind, direction) = match t.kind {
+            TerrainKind::Ice => ("Ice", String::new()),
+            TerrainKind::Mud => ("Mud", String::new()),
+            TerrainKind::Conveyor(direction) => ("Conveyor", format!("{:?}", direction)),
+        };
+        let fields = vec![json!(kind), json!(direction), json!(t.dimension.to_string())];
+        entities.push(terrain.instance(&mut ids, t.x, t.y, fields));
+    }
+    for c in &map_data.checkpoints {
+        entities.push(checkpoint.instance(&mut ids, c.x, c.y, vec![json!(c.dimension.to_string())]));
+    }
+    for c in &map_data.collectibles {
+        entities.push(collectible.instance(&mut ids, c.x, c.y, vec![json!(c.dimension.to_string())]));
+    }
+    for z in &map_data.switch_zones {
+        let (kind, target, period) = match z.kind {
+            ZoneKind::Block => ("Block", String::new(), 0.),
+            ZoneKind::Force(dimension) => ("Force", dimension.to_string(), 0.),
+            ZoneKind::Flip { period } => ("Flip", String::new(), period),
+        };
+        let fields = vec![json!(z.width), json!(z.height), json!(kind), json!(target), json!(period)];
+        entities.push(switch_zone.instance(&mut ids, z.x, z.y, fields));
+    }
+    for l in &map_data.lights {
+        let [r, g, b] = l.color.map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+        let color = format!("#{:02X}{:02X}{:02X}", r, g, b);
+        let fields = vec![json!(l.radius), json!(color), json!(l.flicker), json!(l.dimension.to_string())];
+        entities.push(light.instance(&mut ids, l.x, l.y, fields));
+    }
+    for b in &map_data.blocks {
+        entities.push(block.instance(&mut ids, b.x, b.y, vec![json!(b.dimension.to_string())]));
+    }
+    for h in &map_data.hazards {
+        let fields = vec![json!(format!("{:?}", h.kind)), json!(h.period), json!(h.active), json!(h.phase), json!(h.dimension.to_string())];
+        entities.push(hazard.instance(&mut ids, h.x, h.y, fields));
+    }
+    for s in &map_data.signs {
+        let fields = vec![json!(s.text), json!(s.radius), json!(s.dimension.to_string())];
+        entities.push(sign.instance(&mut ids, s.x, s.y, fields));
+    }
+    for e in &map_data.energy_pickups {
+        entities.push(energy_pickup.instance(&mut ids, e.x, e.y, vec![json!(e.amount), json!(e.dimension.to_string())]));
+    }
+
+    // Level fields hold the map settings
+    let settings = [
+        ("id", "String", json!(map_data.id)),
+        ("max_speed", "Float", json!(map_data.movement.max_speed)),
+        ("acceleration", "Float", json!(map_data.movement.acceleration)),
+        ("friction", "Float", json!(map_data.movement.friction)),
+        ("lives", "Int", json!(map_data.lives)),
+        ("forgiving_collisions", "Bool", json!(map_data.forgiving_collisions)),
+        // "Instant" or "Inventory"
+        ("key_rule", "String", json!(format!("{:?}", map_data.key_rule))),
+        // A limit of 0 uses means no limit
+        ("peek_enabled", "Bool", json!(map_data.abilities.peek.enabled)),
+        ("peek_cooldown", "Float", json!(map_data.abilities.peek.cooldown)),
+        ("peek_uses", "Int", json!(map_data.abilities.peek.uses)),
+        ("sonar_enabled", "Bool", json!(map_data.abilities.sonar.enabled)),
+        ("sonar_cooldown", "Float", json!(map_data.abilities.sonar.cooldown)),
+        ("sonar_uses", "Int", json!(map_data.abilities.sonar.uses)),
+        ("dash_enabled", "Bool", json!(map_data.abilities.dash.enabled)),
+        ("dash_cooldown", "Float", json!(map_data.abilities.dash.cooldown)),
+        ("dash_uses", "Int", json!(map_data.abilities.dash.uses)),
+        // A maximum of 0 makes switching free
+        ("energy_max", "Float", json!(map_data.energy.map_or(0., |e| e.max))),
+        ("energy_cost", "Float", json!(map_data.energy.map_or(0., |e| e.cost))),
+        ("energy_regen", "Float", json!(map_data.energy.map_or(0., |e| e.regen))),
+        // The tutorial steps as written in the map JSON
+        ("tutorial", "String", json!(serde_json::to_string(&map_data.tutorial)?)),
+    ];
+    let settings: Vec<(&str, &str, i64, Value)> = settings.into_iter().map(|(identifier, field_type, value)| (identifier, field_type, ids.uid(), value)).collect();
+    project["defs"]["levelFields"] = json!(settings.iter().map(|(identifier, field_type, uid, _)| field_def(identifier, field_type, *uid)).collect::<Vec<_>>());
+
+    // Level
+    let level = &mut project["levels"][0];
+    level["fieldInstances"] = json!(settings.iter().map(|(identifier, field_type, uid, value)| field_instance(identifier, field_type, *uid, value.clone())).collect::<Vec<_>>());
+    level["identifier"] = json!(level_identifier(&map_data.name));
+    level["useAutoIdentifier"] = json!(false);
+    level["pxWid"] = json!(width * GRID_SIZE);
+    level["pxHei"] = json!(height * GRID_SIZE);
+
+    let layers = level["layerInstances"].as_array_mut().ok_or("LDtk template has no layer instances")?;
+    let dimension_layer = layers.first().ok_or("LDtk template has no layer instances")?.clone();
+    layers.clear();
+    for (index, uid) in dimension_layer_uids.iter().enumerate() {
+        let dimension = Dimension(index as u8);
+        // IntGrid values are stored row by row
+        let mut grid = vec![0; (width * height).max(0) as usize];
+        for wall in &map_data.walls {
+            if wall.dimension.contains(dimension) && wall.x >= 0 && wall.x < width && wall.y >= 0 && wall.y < height {
+                grid[(wall.y * width + wall.x) as usize] = WALL_VALUE;
+            }
+        }
+        let mut layer = dimension_layer.clone();
+        layer["__identifier"] = json!(dimension_layer_name(index));
+        layer["layerDefUid"] = json!(uid);
+        layer["iid"] = json!(ids.iid());
+        layer["__cWid"] = json!(width);
+        layer["__cHei"] = json!(height);
+        layer["intGridCsv"] = json!(grid);
+        layers.push(layer);
+    }
+    let mut entity_layer = dimension_layer;
+    entity_layer["__identifier"] = json!("Entities");
+    entity_layer["__type"] = json!("Entities");
+    entity_layer["layerDefUid"] = json!(entity_layer_uid);
+    entity_layer["iid"] = json!(ids.iid());
+    entity_layer["__cWid"] = json!(width);
+    entity_layer["__cHei"] = json!(height);
+    entity_layer["intGridCsv"] = json!([]);
+    entity_layer["entityInstances"] = json!(entities);
+    layers.push(entity_layer);
+
+    project["nextUid"] = json!(ids.next_uid);
+    Ok(serde_json::to_string_pretty(&project)?)
+}
+
+// Written like LDtk writes an entity layer, the IntGrid values and rules only belong to the dimension layers
+fn entity_layer_def(uid: i64) -> Value {
+    json!({
+        "__type": "Entities",
+        "identifier": "Entities",
+        "type": "Entities",
+        "uid": uid,
+        "doc": null,
+        "uiColor": null,
+        "gridSize": GRID_SIZE,
+        "guideGridWid": 0,
+        "guideGridHei": 0,
+        "displayOpacity": 1,
+        "inactiveOpacity": 1,
+        "hideInList": false,
+        "hideFieldsWhenInactive": false,
+        "canSelectWhenInactive": true,
+        "renderInWorldView": true,
+        "pxOffsetX": 0,
+        "pxOffsetY": 0,
+        "parallaxFactorX": 0,
+        "parallaxFactorY": 0,
+        "parallaxScaling": true,
+        "requiredTags": [],
+        "excludedTags": [],
+        "intGridValues": [],
+        "autoRuleGroups": [],
+        "autoSourceLayerDefUid": null,
+        "tilesetDefUid": null,
+        "tilePivotX": 0,
+        "tilePivotY": 0
+    })
+}
+
+fn path_field(path: &[[i32; 2]]) -> String {
+    let points: Vec<String> = path.iter().map(|[x, y]| format!("{},{}", x, y)).collect();
+    points.join(" ")
+}
+
+// Dimension1, Dimension2... as in our own project
+fn dimension_layer_name(index: usize) -> String {
+    format!("Dimension{}", index + 1)
+}
+
+// LDtk identifiers only accept letters, digits and underscores and can't start with a digit
+fn level_identifier(name: &str) -> String {
+    let identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match identifier.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => identifier,
+        _ => format!("Level_{}", identifier),
+    }
+}
+
+struct IdGenerator {
+    next_uid: i64,
+    next_iid: u64,
+}
+impl IdGenerator {
+    fn new(next_uid: i64) -> IdGenerator {
+        IdGenerator { next_uid, next_iid: 0 }
+    }
+
+    fn uid(&mut self) -> i64 {
+        self.next_uid += 1;
+        self.next_uid - 1
+    }
+
+    // LDtk only needs iids to be unique inside the project
+    fn iid(&mut self) -> String {
+        self.next_iid += 1;
+        format!("7e1d0000-0000-4000-8000-{:012x}", self.next_iid)
+    }
+}
+
+struct EntityDef {
+    identifier: &'static str,
+    color: &'static str,
+    uid: i64,
+    // Every entity but the global ones also gets a "dimension" field, written like "Light,Dark,2"
+    fields: Vec<(&'static str, &'static str, i64)>,
+}
+impl EntityDef {
+    fn new(ids: &mut IdGenerator, identifier: &'static str, color: &'static str, own_fields: &[(&'static str, &'static str)]) -> EntityDef {
+        let uid = ids.uid();
+        let mut fields: Vec<(&'static str, &'static str, i64)> = own_fields.iter().map(|(f, t)| (*f, *t, ids.uid())).collect();
+        fields.push(("dimension", "String", ids.uid()));
+        EntityDef { identifier, color, uid, fields }
+    }
+
+    // Entities present in every dimension, without a dimension field
+    fn global(ids: &mut IdGenerator, identifier: &'static str, color: &'static str, own_fields: &[(&'static str, &'static str)]) -> EntityDef {
+        let uid = ids.uid();
+        let fields = own_fields.iter().map(|(f, t)| (*f, *t, ids.uid())).collect();
+        EntityDef { identifier, color, uid, fields }
+    }
+
+    fn to_json(&self) -> Value {
+        let field_defs: Vec<Value> = self.fields.iter().map(|(identifier, field_type, uid)| field_def(identifier, field_type, *uid)).collect();
+
+        json!({
+            "identifier": self.identifier, "uid": self.uid, "tags": [], "exportToToc": false, "doc": null,
+            "width": GRID_SIZE, "height": GRID_SIZE, "resizableX": false, "resizableY": false,
+            "minWidth": null, "maxWidth": null, "minHeight": null, "maxHeight": null, "keepAspectRatio": false,
+            "tileOpacity": 1, "fillOpacity": 1, "lineOpacity": 1, "hollow": false, "color": self.color,
+            "renderMode": "Ellipse", "showName": true, "tilesetId": null, "tileRenderMode": "FitInside",
+            "tileRect": null, "nineSliceBorders": [], "maxCount": 0, "limitScope": "PerLevel",
+            "limitBehavior": "MoveLastOne", "pivotX": 0.5, "pivotY": 0.5, "fieldDefs": field_defs
+        })
+    }
+
+    fn instance(&self, ids: &mut IdGenerator, x: i32, y: i32, values: Vec<Value>) -> Value {
+        let field_instances: Vec<Value> = self.fields.iter().zip(values)
+            .map(|((identifier, field_type, uid), value)| field_instance(identifier, field_type, *uid, value))
+            .collect();
+        let px_x = x * GRID_SIZE + GRID_SIZE / 2;
+        let px_y = y * GRID_SIZE + GRID_SIZE / 2;
+
+        json!({
+            "__identifier": self.identifier, "__grid": [x, y], "__pivot": [0.5, 0.5], "__tags": [], "__tile": null,
+            "__smartColor": self.color, "iid": ids.iid(), "width": GRID_SIZE, "height": GRID_SIZE,
+            "defUid": self.uid, "px": [px_x, px_y], "fieldInstances": field_instances,
+            "__worldX": px_x, "__worldY": px_y
+        })
+    }
+}
+
+fn field_def(identifier: &str, field_type: &str, uid: i64) -> Value {
+    json!({
+        "identifier": identifier, "doc": null, "__type": field_type, "uid": uid, "type": format!("F_{}", field_type),
+        "isArray": false, "canBeNull": false, "arrayMinLength": null, "arrayMaxLength": null,
+        "editorDisplayMode": "NameAndValue", "editorDisplayScale": 1, "editorDisplayPos": "Above",
+        "editorLinkStyle": "StraightArrow", "editorAlwaysShow": false, "editorShowInWorld": true,
+        "editorCutLongValues": true, "editorTextSuffix": null, "editorTextPrefix": null, "useForSmartColor": false,
+        "min": null, "max": null, "regex": null, "acceptFileTypes": null, "defaultOverride": null,
+        "textLanguageMode": null, "symmetricalRef": false, "autoChainRef": true, "allowOutOfLevelRef": true,
+        "allowedRefs": "Any", "allowedRefsEntityUid": null, "allowedRefTags": [], "tilesetUid": null
+    })
+}
+
+fn field_instance(identifier: &str, field_type: &str, uid: i64, value: Value) -> Value {
+    json!({
+        "__identifier": identifier, "__type": field_type, "__value": value.clone(), "__tile": null, "defUid": uid,
+        "realEditorValues": [{ "id": format!("V_{}", field_type), "params": [value] }]
+    })
+}
@@ -3,23 +3,33 @@ use bevy::prelude::Resource;
 
 use map_shared::MapData;
 
+use super::progress::map_key;
+
 #[derive(Resource)]
 pub struct MapManager {
     pub tuto_map: MapData,
     pub level1_map: MapData,
     pub custom_map: Option<MapData>,
+    // Progress keys of the maps, encoding them every frame in the menu would be too slow
+    pub tuto_key: String,
+    pub level1_key: String,
+    pub custom_key: Option<String>,
 }
 
 impl MapManager {
     pub fn new(tuto_map: MapData, level_map: MapData) -> Result<Self, Box<dyn Error>> {
         Ok(Self {
+            tuto_key: map_key(&tuto_map),
+            level1_key: map_key(&level_map),
             tuto_map,
             level1_map: level_map,
             custom_map: None,
+            custom_key: None,
         })
     }
 
     pub fn set_custom_map(&mut self, custom_map: MapData) {
+        self.custom_key = Some(map_key(&custom_map));
         self.custom_map = Some(custom_map)
     }
 }
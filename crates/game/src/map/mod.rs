@@ -1,2 +1,6 @@
+pub mod code;
+pub mod ldtk;
 pub mod map_manager;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod progress;
+pub mod png;
@@ -1,7 +1,9 @@
 use std::path::PathBuf;
-use std::{fs, error::Error, str::FromStr};
+use std::{fs, error::Error};
 
-use map_shared::{Dimension, MapData};
+use map_shared::{Dimension, DimensionMask, KeyRule, MapData, StepAction, StepTrigger, ZoneKind, MAX_DIMENSIONS, MAX_LIGHTS};
+
+use super::{code::decode_map, png::{import_png, PngSource}};
 
 pub enum MapFile {
     Level,
@@ -11,25 +13,297 @@ pub enum MapFile {
 pub enum MapSource {
     FilePath(PathBuf),
     FileContent(MapFile),
+    Code(String),
+    Png(PngSource),
 }
 
 pub fn parse_map(map_source: MapSource) -> Result<MapData, Box<dyn Error>> {
-    let file = match map_source {
-        MapSource::FilePath(path) => fs::read_to_string(path)?,
+    let data: MapData = match map_source {
+        MapSource::FilePath(path) => serde_json::from_str(&fs::read_to_string(path)?)?,
         MapSource::FileContent(map_file) => match map_file {
-            MapFile::Level => include_str!("data/level.json").to_string(),
-            MapFile::Tuto => include_str!("data/tuto.json").to_string(),
+            MapFile::Level => serde_json::from_str(include_str!("data/level.json"))?,
+            MapFile::Tuto => serde_json::from_str(include_str!("data/tuto.json"))?,
         },
+        MapSource::Code(code) => decode_map(&code)?,
+        MapSource::Png(source) => import_png(&source)?,
     };
 
-    let data: MapData = serde_json::from_str(&file)?;
-    
+    validate_map(&data)?;
+    Ok(data)
+}
+
+pub fn validate_map(data: &MapData) -> Result<(), Box<dyn Error>> {
     // Check if the size is a multiple of 16
     if data.size % 16 != 0 {
         return Err("Map size must be a multiple of 16".into());
     }
-    Ok(data)
+
+    let count = data.dimensions.len();
+    if count == 0 || count > MAX_DIMENSIONS {
+        return Err(format!("Map must define between 1 and {} dimensions", MAX_DIMENSIONS).into());
+    }
+    // Every dimension is rendered by its own camera
+    let mut layers = Vec::new();
+    for index in 0..count {
+        let layer = data.render_layer(Dimension(index as u8));
+        if layer == 0 || layer as usize > MAX_DIMENSIONS || layers.contains(&layer) {
+            return Err(format!("Dimension {} needs its own render layer between 1 and {}", index, MAX_DIMENSIONS).into());
+        }
+        layers.push(layer);
+    }
+
+    let valid = DimensionMask::all(count);
+    let masks = data.walls.iter().map(|w| w.dimension)
+        .chain(data.doors.iter().map(|d| d.dimension))
+        .chain(data.keys.iter().map(|k| k.dimension))
+        .chain(data.portals.iter().map(|p| p.dimension))
+        .chain(data.gates.iter().map(|g| g.dimension))
+        .chain(data.switches.iter().map(|s| s.dimension))
+        .chain(data.moving_walls.iter().map(|m| m.dimension))
+        .chain(data.one_ways.iter().map(|o| o.dimension))
+        .chain(data.terrains.iter().map(|t| t.dimension))
+        .chain(data.checkpoints.iter().map(|c| c.dimension))
+        .chain(data.collectibles.iter().map(|c| c.dimension))
+        .chain(data.energy_pickups.iter().map(|e| e.dimension))
+        .chain(data.lights.iter().map(|l| l.dimension))
+        .chain(data.blocks.iter().map(|b| b.dimension))
+        .chain(data.signs.iter().map(|s| s.dimension));
+    for mask in masks {
+        if mask.0 == 0 {
+            return Err("Map item is not in any dimension".into());
+        }
+        if mask.0 & !valid.0 != 0 {
+            return Err(format!("Map item is in dimensions {} but the map only has {}", mask, count).into());
+        }
+    }
+
+    // Every portal must lead to exactly one other portal
+    for portal in &data.portals {
+        if data.portals.iter().filter(|p| p.id == portal.id).count() > 1 {
+            return Err(format!("Portal id {} is used more than once", portal.id).into());
+        }
+        if portal.exit_id == portal.id {
+            return Err(format!("Portal {} leads to itself", portal.id).into());
+        }
+        let Some(exit) = data.portals.iter().find(|p| p.id == portal.exit_id) else {
+            return Err(format!("Portal {} leads to portal {} which does not exist", portal.id, portal.exit_id).into());
+        };
+        if let Some(exit_dimension) = portal.exit_dimension {
+            if exit_dimension.index() >= count {
+                return Err(format!("Portal {} leads to dimension {} but the map only has {}", portal.id, exit_dimension, count).into());
+            }
+        }
+        // The player comes out in the exit dimension, or in the one it entered from
+        let arrivals: Vec<Dimension> = match portal.exit_dimension {
+            Some(exit_dimension) => vec![exit_dimension],
+            None => portal.dimension.dimensions().collect(),
+        };
+        for dimension in arrivals {
+            if !exit.dimension.contains(dimension) {
+                return Err(format!("Portal {} leads to portal {} which is not in dimension {}", portal.id, exit.id, dimension).into());
+            }
+            let blocked = data.walls.iter().any(|w| w.x == exit.x && w.y == exit.y && w.dimension.contains(dimension))
+                || data.blocks.iter().any(|b| b.x == exit.x && b.y == exit.y && b.dimension.contains(dimension));
+            if blocked {
+                return Err(format!("Portal {} leads to ({}, {}) which is blocked in dimension {}", portal.id, exit.x, exit.y, dimension).into());
+            }
+        }
+    }
+
+    if let Some(door) = data.doors.iter().find(|d| !d.timer.is_finite() || d.timer < 0.) {
+        return Err(format!("Door at ({}, {}) has an invalid timer {}", door.x, door.y, door.timer).into());
+    }
+    // The consumed keys are gone, a door closing again would lock the player out
+    if let Some(door) = data.doors.iter().find(|d| d.consume && d.timer > 0.) {
+        return Err(format!("Door at ({}, {}) consumes its keys so it can't close again", door.x, door.y).into());
+    }
+    // With the inventory every door must be openable with the keys of the map
+    if data.key_rule == KeyRule::Inventory {
+        for door in &data.doors {
+            let keys = data.keys.iter().filter(|k| if door.generic { k.generic } else { !k.generic && k.door_id == door.id }).count();
+            if door.keys_needed == 0 || door.keys_needed as usize > keys {
+                return Err(format!("Door at ({}, {}) needs {} keys but the map has {}", door.x, door.y, door.keys_needed, keys).into());
+            }
+        }
+    }
+
+    let height = (data.size / 16) * 9;
+    for zone in &data.switch_zones {
+        if zone.width <= 0 || zone.height <= 0 || zone.x < 0 || zone.y < 0 || zone.x + zone.width > data.size || zone.y + zone.height > height {
+            return Err(format!("Switch zone at ({}, {}) must have a size and stay inside the map", zone.x, zone.y).into());
+        }
+        match zone.kind {
+            ZoneKind::Block => (),
+            ZoneKind::Force(dimension) if dimension.index() >= count => {
+                return Err(format!("Switch zone at ({}, {}) forces dimension {} but the map only has {}", zone.x, zone.y, dimension, count).into());
+            }
+            ZoneKind::Force(_) => (),
+            ZoneKind::Flip { period } if !period.is_finite() || period <= 0. => {
+                return Err(format!("Switch zone at ({}, {}) has an invalid period {}", zone.x, zone.y, period).into());
+            }
+            ZoneKind::Flip { .. } => (),
+        }
+    }
+
+    for light in &data.lights {
+        let valid = light.radius.is_finite() && light.radius > 0.
+            && (0. ..=1.).contains(&light.flicker)
+            && light.color.iter().all(|c| (0. ..=1.).contains(c));
+        if !valid {
+            return Err(format!("Light at ({}, {}) needs a positive radius, a flicker and colour between 0 and 1", light.x, light.y).into());
+        }
+    }
+    // Every light of a dimension goes in the same shader
+    for index in 0..count {
+        let lights = data.lights.iter().filter(|l| l.dimension.contains(Dimension(index as u8))).count();
+        if lights > MAX_LIGHTS {
+            return Err(format!("Dimension {} has {} lights, the maximum is {}", index, lights, MAX_LIGHTS).into());
+        }
+    }
+
+    for hazard in &data.hazards {
+        if hazard.dimension.index() >= count {
+            return Err(format!("Hazard is in dimension {} but the map only has {}", hazard.dimension, count).into());
+        }
+        let valid = hazard.period.is_finite() && hazard.period > 0.
+            && hazard.active.is_finite() && hazard.active > 0. && hazard.active <= hazard.period
+            && hazard.phase.is_finite();
+        if !valid {
+            return Err(format!("Hazard at ({}, {}) needs a positive period with an active time within it", hazard.x, hazard.y).into());
+        }
+    }
+
+    for sign in &data.signs {
+        if sign.text.trim().is_empty() || !sign.radius.is_finite() || sign.radius <= 0. {
+            return Err(format!("Sign at ({}, {}) needs a text and a positive radius", sign.x, sign.y).into());
+        }
+    }
+
+    let inside = |x: i32, y: i32| x >= 0 && x < data.size && y >= 0 && y < height;
+    for (index, step) in data.tutorial.iter().enumerate() {
+        let trigger_valid = match step.trigger {
+            StepTrigger::Region { x, y, width, height: rows } => width > 0 && rows > 0 && inside(x, y) && inside(x + width - 1, y + rows - 1),
+            StepTrigger::After(previous) => previous < data.tutorial.len() && previous != index,
+            StepTrigger::Start | StepTrigger::Event(_) => true,
+        };
+        if !trigger_valid {
+            return Err(format!("Tutorial step {} has an invalid trigger", index).into());
+        }
+        let marks_valid = step.actions.iter().all(|action| match *action {
+            StepAction::Highlight { x, y } | StepAction::Arrow { x, y, .. } => inside(x, y),
+            StepAction::Enable(_) | StepAction::Disable(_) => true,
+        });
+        if !marks_valid {
+            return Err(format!("Tutorial step {} marks a cell outside the map", index).into());
+        }
+    }
+
+    // Two blocks can't share a cell in the same dimension
+    for (index, block) in data.blocks.iter().enumerate() {
+        if data.blocks[index + 1..].iter().any(|b| b.x == block.x && b.y == block.y && b.dimension.0 & block.dimension.0 != 0) {
+            return Err(format!("Two blocks are at ({}, {}) in the same dimension", block.x, block.y).into());
+        }
+    }
+
+    for switch in &data.switches {
+        if !data.gates.iter().any(|g| g.group == switch.group) {
+            return Err(format!("Switch at ({}, {}) controls group {} which has no gate", switch.x, switch.y, switch.group).into());
+        }
+    }
+
+    let movement = [data.movement.max_speed, data.movement.acceleration, data.movement.friction];
+    if movement.iter().any(|value| !value.is_finite() || *value <= 0.) {
+        return Err("Movement speed, acceleration and friction must be positive".into());
+    }
+    let abilities = [data.abilities.peek, data.abilities.sonar, data.abilities.dash];
+    if abilities.iter().any(|ability| !ability.cooldown.is_finite() || ability.cooldown < 0.) {
+        return Err("Ability cooldowns must be positive".into());
+    }
+    if let Some(energy) = data.energy {
+        let valid = energy.max.is_finite() && energy.max > 0.
+            && energy.cost.is_finite() && energy.cost >= 0. && energy.cost <= energy.max
+            && energy.regen.is_finite() && energy.regen >= 0.;
+        if !valid {
+            return Err("Energy needs a positive maximum, a cost below it and a positive refill".into());
+        }
+    }
+    if let Some(pickup) = data.energy_pickups.iter().find(|e| !e.amount.is_finite() || e.amount <= 0.) {
+        return Err(format!("Energy pickup at ({}, {}) has an invalid amount {}", pickup.x, pickup.y, pickup.amount).into());
+    }
+    if data.lives == 0 {
+        return Err("Map must give at least one life".into());
+    }
+
+    for moving_wall in &data.moving_walls {
+        validate_path(data, "Moving wall", &moving_wall.path, moving_wall.speed)?;
+    }
+    for sentry in &data.sentries {
+        validate_path(data, "Sentry", &sentry.path, sentry.speed)?;
+        if sentry.dimension.index() >= count {
+            return Err(format!("Sentry is in dimension {} but the map only has {}", sentry.dimension, count).into());
+        }
+        if !sentry.chase_radius.is_finite() || sentry.chase_radius < 0. {
+            return Err(format!("Sentry chase radius {} is invalid", sentry.chase_radius).into());
+        }
+    }
+    Ok(())
+}
+
+fn validate_path(data: &MapData, name: &str, path: &[[i32; 2]], speed: f32) -> Result<(), Box<dyn Error>> {
+    let height = (data.size / 16) * 9;
+    if path.is_empty() {
+        return Err(format!("{} has no waypoint", name).into());
+    }
+    if !speed.is_finite() || speed < 0. {
+        return Err(format!("{} speed {} is invalid", name, speed).into());
+    }
+    if let Some([x, y]) = path.iter().find(|[x, y]| *x < 0 || *x >= data.size || *y < 0 || *y >= height) {
+        return Err(format!("{} waypoint ({}, {}) is outside the map", name, x, y).into());
+    }
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory_map(door: &str) -> MapData {
+        let json = format!(r#"{{"name":"doors","size":16,"start_x":0,"start_y":0,"goal_x":15,"goal_y":8,
+            "walls":[],"doors":[{}],"keys":[{{"x":2,"y":2,"door_id":1,"dimension":"Light"}}],
+            "key_rule":"Inventory"}}"#, door);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn consuming_door_without_timer_is_valid() {
+        let data = inventory_map(r#"{"x":5,"y":5,"id":1,"consume":true,"dimension":"Light"}"#);
+        assert!(validate_map(&data).is_ok());
+    }
+
+    #[test]
+    fn consuming_door_with_timer_is_rejected() {
+        let data = inventory_map(r#"{"x":5,"y":5,"id":1,"consume":true,"timer":3,"dimension":"Light"}"#);
+        assert!(validate_map(&data).is_err());
+    }
 
+    fn portal_map(walls: &str, exit_dimension: &str) -> MapData {
+        let json = format!(r#"{{"name":"portals","size":16,"start_x":0,"start_y":0,"goal_x":15,"goal_y":8,
+            "walls":{},"doors":[],"keys":[],
+            "portals":[{{"x":2,"y":2,"id":1,"exit_id":2,"dimension":"Light","exit_dimension":{}}},
+                {{"x":8,"y":2,"id":2,"exit_id":1,"dimension":"Dark","exit_dimension":"Light"}}]}}"#, walls, exit_dimension);
+        serde_json::from_str(&json).unwrap()
+    }
 
+    #[test]
+    fn portal_exit_missing_from_the_arrival_dimension_is_rejected() {
+        assert!(validate_map(&portal_map("[]", r#""Dark""#)).is_ok());
+        let error = validate_map(&portal_map("[]", "null")).unwrap_err();
+        assert!(error.to_string().contains("which is not in dimension"));
+    }
+
+    #[test]
+    fn portal_exit_inside_a_wall_is_rejected() {
+        let error = validate_map(&portal_map(r#"[{"x":8,"y":2,"dimension":"Dark"}]"#, r#""Dark""#)).unwrap_err();
+        assert!(error.to_string().contains("which is blocked in dimension"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::error::Error;
+
+use map_shared::{
+    default_keys_needed, default_lives, AbilitiesData, Block, Checkpoint, Collectible, Dimension, DimensionData, DimensionMask, Direction, Door, Gate, Key, KeyRule,
+    MapData, MovementData, OneWay, OneWayKind, Portal, Switch, SwitchKind, Terrain, TerrainKind, Wall, MAX_DIMENSIONS,
+};
+use serde::{Deserialize, Serialize};
+
+// Ids generated by the default legends
+const LEGEND_IDS: u32 = 32;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegendItem {
+    Wall,
+    Door(u32),
+    Key(u32),
+    Start,
+    Goal,
+    Checkpoint,
+    Collectible,
+    Block,
+    // The items below carry extra data so they are only available in custom legends
+    Portal { id: u32, exit_id: u32, exit_dimension: Option<Dimension> },
+    Gate { group: u32, open: bool },
+    Switch { group: u32, kind: SwitchKind },
+    OneWay { direction: Direction, kind: OneWayKind },
+    Terrain(TerrainKind),
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LegendEntry {
+    pub color: [u8; 3],
+    pub item: LegendItem,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PngLegend {
+    pub entries: Vec<LegendEntry>,
+}
+impl PngLegend {
+    /*
+        Legend for one image per dimension
+        Wall black, start green, goal blue, checkpoint magenta, collectible cyan, block brown
+        Door red and key yellow, the blue channel is the door id
+    */
+    pub fn colors() -> PngLegend {
+        let mut entries = vec![
+            LegendEntry { color: [0, 0, 0], item: LegendItem::Wall },
+            LegendEntry { color: [0, 255, 0], item: LegendItem::Start },
+            LegendEntry { color: [0, 0, 255], item: LegendItem::Goal },
+            LegendEntry { color: [255, 0, 255], item: LegendItem::Checkpoint },
+            LegendEntry { color: [0, 255, 255], item: LegendItem::Collectible },
+            LegendEntry { color: [128, 64, 0], item: LegendItem::Block },
+        ];
+        for id in 1..=LEGEND_IDS {
+            entries.push(LegendEntry { color: [255, 0, id as u8], item: LegendItem::Door(id) });
+            entries.push(LegendEntry { color: [255, 255, id as u8], item: LegendItem::Key(id) });
+        }
+        PngLegend { entries }
+    }
+
+    /*
+        Legend for a channel image, every channel is a grey level
+        Wall 0, block 16, checkpoint 32, start 64, collectible 96, goal 128, door 128 + id, key 192 + id
+    */
+    pub fn greys() -> PngLegend {
+        let mut entries = vec![
+            LegendEntry { color: [0, 0, 0], item: LegendItem::Wall },
+            LegendEntry { color: [64, 64, 64], item: LegendItem::Start },
+            LegendEntry { color: [128, 128, 128], item: LegendItem::Goal },
+            LegendEntry { color: [32, 32, 32], item: LegendItem::Checkpoint },
+            LegendEntry { color: [96, 96, 96], item: LegendItem::Collectible },
+            LegendEntry { color: [16, 16, 16], item: LegendItem::Block },
+        ];
+        for id in 1..=LEGEND_IDS {
+            let door = 128 + id as u8;
+            let key = 192 + id as u8;
+            entries.push(LegendEntry { color: [door, door, door], item: LegendItem::Door(id) });
+            entries.push(LegendEntry { color: [key, key, key], item: LegendItem::Key(id) });
+        }
+        PngLegend { entries }
+    }
+
+    fn item(&self, color: [u8; 3]) -> Option<LegendItem> {
+        self.entries.iter().find(|e| e.color == color).map(|e| e.item)
+    }
+}
+
+pub enum PngLayout {
+    // One image per dimension, in dimension order
+    PerDimension(Vec<Vec<u8>>),
+    // A single image, the red channel is the light dimension and the blue channel the dark one
+    Channels(Vec<u8>),
+}
+
+pub struct PngSource {
+    pub name: String,
+    pub layout: PngLayout,
+    pub legend: PngLegend,
+}
+
+pub fn import_png(source: &PngSource) -> Result<MapData, Box<dyn Error>> {
+    let layers = match &source.layout {
+        PngLayout::PerDimension(images) => {
+            if images.is_empty() || images.len() > MAX_DIMENSIONS {
+                return Err(format!("Expected between 1 and {} dimension images", MAX_DIMENSIONS).into());
+            }
+            images
+                .iter()
+                .enumerate()
+                .map(|(index, image)| Ok((Dimension(index as u8), decode_png(image)?)))
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?
+        }
+        PngLayout::Channels(image) => {
+            let image = decode_png(image)?;
+            vec![
+                (Dimension::LIGHT, image.channel(0)),
+                (Dimension::DARK, image.channel(2)),
+            ]
+        }
+    };
+
+    let (width, height) = (layers[0].1.width, layers[0].1.height);
+    if layers.iter().any(|(_, image)| image.width != width || image.height != height) {
+        return Err("All dimension images must have the same size".into());
+    }
+    if width % 16 != 0 || height != (width / 16) * 9 {
+        return Err(format!("Image is {}x{}, it must be 16:9 with a width multiple of 16", width, height).into());
+    }
+
+    let mut map_data = MapData {
+        name: source.name.clone(),
+        id: String::new(),
+        size: width as i32,
+        start_x: 0,
+        start_y: 0,
+        goal_x: 0,
+        goal_y: 0,
+        dimensions: (0..layers.len()).map(DimensionData::generated).collect(),
+        walls: Vec::new(),
+        doors: Vec::new(),
+        keys: Vec::new(),
+        portals: Vec::new(),
+        gates: Vec::new(),
+        switches: Vec::new(),
+        moving_walls: Vec::new(),
+        sentries: Vec::new(),
+        one_ways: Vec::new(),
+        terrains: Vec::new(),
+        movement: MovementData::default(),
+        checkpoints: Vec::new(),
+        lives: default_lives(),
+        forgiving_collisions: false,
+        key_rule: KeyRule::default(),
+        collectibles: Vec::new(),
+        abilities: AbilitiesData::default(),
+        energy: None,
+        energy_pickups: Vec::new(),
+        switch_zones: Vec::new(),
+        lights: Vec::new(),
+        blocks: Vec::new(),
+        hazards: Vec::new(),
+        signs: Vec::new(),
+        tutorial: Vec::new(),
+    };
+    let mut start = None;
+    let mut goal = None;
+
+    for (dimension, image) in &layers {
+        let dimension = DimensionMask::from(*dimension);
+        for y in 0..height {
+            for x in 0..width {
+                let Some(color) = image.at(x, y) else { continue };
+                let (x, y) = (x as i32, y as i32);
+                match source.legend.item(color) {
+                    Some(LegendItem::Wall) => map_data.walls.push(Wall { x, y, dimension }),
+                    Some(LegendItem::Door(id)) => {
+                        map_data.doors.push(Door { x, y, id, dimension, timer: 0., keys_needed: default_keys_needed(), generic: false, consume: false })
+                    }
+                    Some(LegendItem::Key(door_id)) => map_data.keys.push(Key { x, y, door_id, dimension, generic: false }),
+                    Some(LegendItem::Portal { id, exit_id, exit_dimension }) => {
+                        map_data.portals.push(Portal { x, y, id, exit_id, dimension, exit_dimension })
+                    }
+                    Some(LegendItem::Gate { group, open }) => map_data.gates.push(Gate { x, y, group, dimension, open }),
+                    Some(LegendItem::Switch { group, kind }) => map_data.switches.push(Switch { x, y, group, kind, dimension }),
+                    Some(LegendItem::OneWay { direction, kind }) => map_data.one_ways.push(OneWay { x, y, direction, kind, dimension }),
+                    Some(LegendItem::Terrain(kind)) => map_data.terrains.push(Terrain { x, y, kind, dimension }),
+                    Some(LegendItem::Checkpoint) => map_data.checkpoints.push(Checkpoint { x, y, dimension }),
+                    Some(LegendItem::Collectible) => map_data.collectibles.push(Collectible { x, y, dimension }),
+                    Some(LegendItem::Block) => map_data.blocks.push(Block { x, y, dimension }),
+                    Some(LegendItem::Start) => set_unique(&mut start, (x, y), "start")?,
+                    Some(LegendItem::Goal) => set_unique(&mut goal, (x, y), "goal")?,
+                    None => (),
+                }
+            }
+        }
+    }
+
+    (map_data.start_x, map_data.start_y) = start.ok_or("Image has no start")?;
+    (map_data.goal_x, map_data.goal_y) = goal.ok_or("Image has no goal")?;
+    Ok(map_data)
+}
+
+// Start and goal exist in every dimension, they may be drawn in several images but only at one place
+fn set_unique(slot: &mut Option<(i32, i32)>, position: (i32, i32), name: &str) -> Result<(), Box<dyn Error>> {
+    match slot {
+        Some(current) if *current != position => Err(format!(
+            "Image has more than one {}: ({}, {}) and ({}, {})",
+            name, current.0, current.1, position.0, position.1
+        ).into()),
+        _ => {
+            *slot = Some(position);
+            Ok(())
+        }
+    }
+}
+
+struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+impl RgbaImage {
+    // Transparent pixels are empty cells
+    fn at(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        let [r, g, b, a] = self.pixels[(y * self.width + x) as usize];
+        if a < 128 {
+            None
+        } else {
+            Some([r, g, b])
+        }
+    }
+
+    fn channel(&self, channel: usize) -> RgbaImage {
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|p| [p[channel], p[channel], p[channel], p[3]]).collect(),
+        }
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<RgbaImage, Box<dyn Error>> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Expand palettes and strip 16 bits channels so we only deal with 8 bits colors
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let data = &buffer[..info.buffer_size()];
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+        png::ColorType::Rgb => data.chunks_exact(3).map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => data.chunks_exact(2).map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => data.iter().map(|v| [*v, *v, *v, 255]).collect(),
+        png::ColorType::Indexed => return Err("Indexed PNG could not be expanded".into()),
+    };
+
+    Ok(RgbaImage {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
@@ -0,0 +1,67 @@
+use std::{collections::HashMap, error::Error, fs};
+
+use bevy::prelude::{warn, Resource};
+use map_shared::MapData;
+use serde::{Deserialize, Serialize};
+
+// Written next to where the game is started, the web build keeps the progress of the session only
+const PROGRESS_FILE: &str = "progress.json";
+
+// Best completion reached in every won level, by map key
+#[derive(Resource, Deserialize, Serialize, Default)]
+pub struct Progress {
+    pub best: HashMap<String, u32>,
+}
+
+impl Progress {
+    pub fn load() -> Progress {
+        match fs::read_to_string(PROGRESS_FILE) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid progress file: {}", e);
+                Progress::default()
+            }),
+            Err(_) => Progress::default(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::write(PROGRESS_FILE, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // There is no file system in the browser
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    pub fn best(&self, level: &str) -> Option<u32> {
+        self.best.get(level).copied()
+    }
+
+    // Keep the completion if it beats the best one, returns whether it did
+    pub fn record(&mut self, level: &str, completion: u32) -> bool {
+        match self.best.get(level) {
+            Some(best) if *best >= completion => false,
+            _ => {
+                self.best.insert(level.to_string(), completion);
+                true
+            }
+        }
+    }
+}
+
+// Maps are told apart by their id, or by their content so an imported map named like a built-in one keeps its own best
+pub fn map_key(map_data: &MapData) -> String {
+    if !map_data.id.is_empty() {
+        return map_data.id.clone();
+    }
+    match serde_json::to_string(map_data) {
+        Ok(json) => format!("{:08x}", crc32fast::hash(json.as_bytes())),
+        Err(e) => {
+            warn!("Failed to serialize the map {} for its progress: {}", map_data.name, e);
+            map_data.name.clone()
+        }
+    }
+}
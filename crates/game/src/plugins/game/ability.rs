@@ -0,0 +1,69 @@
+use bevy::{
+    prelude::{Assets, Camera2d, Handle, Query, Res, ResMut, Vec2, With},
+    time::Time,
+};
+
+use crate::plugins::input::types::InputData;
+
+use super::{
+    dimension::{show_dimension, DimensionHandle},
+    engine::GameData,
+    shader::DimensionMaterial,
+    systems::FullScreen,
+    tutorial::Tutorial,
+};
+
+// Seconds the next dimension stays on screen
+const PEEK_DURATION: f32 = 1.;
+const SONAR_DURATION: f32 = 1.5;
+// Cells around the player
+const SONAR_RADIUS: f32 = 5.;
+const DASH_DURATION: f32 = 0.2;
+// Times the max speed
+pub(crate) const DASH_SPEED: f32 = 3.;
+
+// B peeks, X sends a sonar pulse and Y dashes
+#[allow(clippy::too_many_arguments)]
+pub fn ability_system(
+    time: Res<Time>,
+    input_data: Res<InputData>,
+    tutorial: Res<Tutorial>,
+    dimension: Res<DimensionHandle>,
+    mut game_data: ResMut<GameData>,
+    mut materials: ResMut<Assets<DimensionMaterial>>,
+    mut texture_query: Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
+    mut camera_query: Query<&mut Camera2d, With<FullScreen>>,
+) {
+    // Abilities wait while a message is shown
+    if tutorial.current_message_index.is_some() {
+        return;
+    }
+    let delta = time.delta_seconds();
+
+    // Peeking needs another dimension to look at
+    let can_peek = game_data.dimension_enabled && game_data.dimension_count() > 1;
+    if input_data.button_b && can_peek && game_data.abilities.peek.trigger(PEEK_DURATION) {
+        let mut next = game_data.dimension;
+        next.switch_dimension(game_data.dimension_count());
+        show_dimension(next, &dimension, &mut texture_query, &mut camera_query);
+    }
+    if game_data.abilities.peek.tick(delta) {
+        show_dimension(game_data.dimension, &dimension, &mut texture_query, &mut camera_query);
+    }
+
+    if input_data.button_x {
+        game_data.abilities.sonar.trigger(SONAR_DURATION);
+    }
+    game_data.abilities.sonar.tick(delta);
+    // The pulse fades out until the end of the effect
+    let strength = game_data.abilities.sonar.active.max(0.) / SONAR_DURATION;
+    for handle in dimension.get_shader_handles() {
+        let material = materials.get_mut(handle).unwrap();
+        material.shader_data.sonar = Vec2::new(SONAR_RADIUS, strength);
+    }
+
+    if input_data.button_y {
+        game_data.abilities.dash.trigger(DASH_DURATION);
+    }
+    game_data.abilities.dash.tick(delta);
+}
@@ -1,65 +1,55 @@
 use super::{
     engine::{GameData, SizeDate},
-    map::{ItemType, Door, Key},
-    systems::{PlayerPosition, GameEntity, DoorId}, shader::{DimensionMaterial, ShaderData},
+    map::{ItemType, Key, Gate, Map, OneWay, Switch},
+    systems::{PlayerPosition, GameEntity, BlockId, CollectibleItem, DoorCountdown, DoorWall, EnergyItem, HazardItem, FullScreen, GateWall, KeyItem, MovingWallId, OneWayDoorWall, SentryId}, shader::{DimensionMaterial, ShaderData},
 };
-use map_shared::Dimension;
+use map_shared::{Dimension, Direction, Hazard, HazardKind, OneWayKind, Sign, SwitchKind, SwitchZone, TerrainKind, ZoneKind, MAX_LIGHTS};
 use bevy::{
     core_pipeline::clear_color::ClearColorConfig,
     prelude::{
-        default, shape, Assets, Camera2dBundle, Color, Commands, Handle, Image, Mesh,
-        Resource, Transform, Vec2, Vec3,
+        default, shape, Assets, Camera2d, Camera2dBundle, Color, Commands, Handle, Image, Mesh,
+        Quat, Query, Resource, Transform, Vec2, Vec3, Vec4, Visibility, With,
     },
     render::{camera::RenderTarget, view::RenderLayers},
     sprite::{ColorMaterial, MaterialMesh2dBundle},
 };
 
+struct DimensionView {
+    image: Handle<Image>,
+    shader: Handle<DimensionMaterial>,
+    layer: RenderLayers,
+    front_color: Color,
+    other_color: Color,
+    clear_color: Color,
+}
+
 #[derive(Resource)]
 pub struct DimensionHandle {
-    light_image: Handle<Image>,
-    dark_image: Handle<Image>,
-    light_shader: Handle<DimensionMaterial>,
-    dark_shader: Handle<DimensionMaterial>,
-    light_layer: RenderLayers,
-    dark_layer: RenderLayers,
-    light_color: Color,
-    dark_color: Color,
-    light_dark: Color,
-    dark_light: Color
+    views: Vec<DimensionView>,
 }
 impl DimensionHandle {
     pub fn get_image_handle(&self, dimension: Dimension) -> Handle<Image> {
-        match dimension {
-            Dimension::Light => self.light_image.clone(),
-            Dimension::Dark => self.dark_image.clone(),
-        }
+        self.views[dimension.index()].image.clone()
     }
     pub fn get_render_layer(&self, dimension: Dimension) -> RenderLayers {
-        match dimension {
-            Dimension::Light => self.light_layer,
-            Dimension::Dark => self.dark_layer,
-        }
+        self.views[dimension.index()].layer
     }
 
     pub fn get_color(&self, dimension: Dimension) -> (Color, Color) {
-        match dimension {
-            Dimension::Light => (self.dark_color, self.dark_light),
-            Dimension::Dark => (self.light_color, self.light_dark)
-        }
+        let view = &self.views[dimension.index()];
+        (view.front_color, view.other_color)
     }
     
     pub(crate) fn get_shader_handle(&self, dimension: Dimension) -> Handle<DimensionMaterial> {
-        match dimension {
-            Dimension::Light => self.light_shader.clone(),
-            Dimension::Dark => self.dark_shader.clone(),
-        }
+        self.views[dimension.index()].shader.clone()
+    }
+
+    pub(crate) fn get_shader_handles(&self) -> impl Iterator<Item = &Handle<DimensionMaterial>> {
+        self.views.iter().map(|view| &view.shader)
     }
 
     pub(crate) fn get_clear_color(&self, dimension: Dimension) -> Color {
-        match dimension {
-            Dimension::Light => Color::rgba(1., 1., 1., 1.),
-            Dimension::Dark => Color::rgba(0., 0., 0., 1.),
-        }
+        self.views[dimension.index()].clear_color
     }
 }
 
@@ -70,49 +60,57 @@ pub fn init_dimension(
     materials_shader: &mut Assets<DimensionMaterial>,
     image: Image
 ) -> DimensionHandle {
-    let light_image = images.add(image.clone());
-    let dark_image = images.add(image);
-    let light_layer = RenderLayers::layer(1);
-    let dark_layer = RenderLayers::layer(2);
-    let light_color = Color::rgba(0.95, 0.95, 0.95, 1.);
-    let light_dark = Color::rgba(0.95, 0.95, 0.95, 0.1);
-    let dark_color = Color::rgba(0.05, 0.05, 0.05, 1.);
-    let dark_light = Color::rgba(0.05, 0.05, 0.05, 0.1);
-
-    let light_shader = materials_shader.add(DimensionMaterial {
-        shader_data: ShaderData {
-            player_position: Vec2::new(game_data.player.x, game_data.player.y),
-            player_direction: Vec2::new(1., 0.),
-            size_info: Vec2::new(size_data.grid_x as f32, size_data.grid_y as f32),
-            goal_position: Vec2::new(game_data.player.goal_x as f32, game_data.player.goal_y as f32),
-            color: Color::rgba(0.05, 0.05, 0.05, 1.)
-        },
-        texture: light_image.clone()
-    });
-    let dark_shader = materials_shader.add(DimensionMaterial {
-        shader_data: ShaderData {
-            player_position: Vec2::new(0., 0.),
-            player_direction: Vec2::new(0., 0.),
-            size_info: Vec2::new(size_data.grid_x as f32, size_data.grid_y as f32),
-            goal_position: Vec2::new(game_data.player.goal_x as f32, game_data.player.goal_y as f32),
-            color: Color::rgba(0.95, 0.95, 0.95, 1.)
-        },
-        texture: dark_image.clone()
-    });
-    let dimension_handle = DimensionHandle {
-        light_image,
-        dark_image,
-        light_shader,
-        dark_shader,
-        light_layer,
-        dark_layer,
-        light_color,
-        light_dark,
-        dark_color,
-        dark_light
-    };
+    let views = game_data.dimensions.iter().enumerate().map(|(index, dimension_data)| {
+        let [r, g, b] = dimension_data.foreground;
+        let [clear_r, clear_g, clear_b] = dimension_data.background;
+        let image = images.add(image.clone());
+        let (lights, light_colors, light_count) = light_uniforms(&game_data.map, Dimension(index as u8), 0.);
+        let shader = materials_shader.add(DimensionMaterial {
+            shader_data: ShaderData {
+                player_position: Vec2::new(game_data.player.x, game_data.player.y),
+                player_direction: Vec2::new(game_data.player.dir_x, game_data.player.dir_y),
+                size_info: Vec2::new(size_data.grid_x as f32, size_data.grid_y as f32),
+                goal_position: Vec2::new(game_data.player.goal_x as f32, game_data.player.goal_y as f32),
+                sonar: Vec2::ZERO,
+                color: Color::rgba(r, g, b, 1.),
+                lights,
+                light_colors,
+                light_count,
+            },
+            texture: image.clone()
+        });
+
+        DimensionView {
+            image,
+            shader,
+            layer: RenderLayers::layer(dimension_data.layer(Dimension(index as u8))),
+            front_color: Color::rgba(r, g, b, 1.),
+            other_color: Color::rgba(r, g, b, 0.1),
+            clear_color: Color::rgba(clear_r, clear_g, clear_b, 1.),
+        }
+    }).collect();
+
+    DimensionHandle { views }
+}
 
-    return dimension_handle;
+/*
+    Lights shining in a dimension as the shader wants them, in map order
+    Flickering lights get their brightness for the given time
+*/
+pub(crate) fn light_uniforms(map: &Map, dimension: Dimension, time: f32) -> ([Vec4; MAX_LIGHTS], [Vec4; MAX_LIGHTS], u32) {
+    let mut lights = [Vec4::ZERO; MAX_LIGHTS];
+    let mut colors = [Vec4::ZERO; MAX_LIGHTS];
+    let shining: Vec<_> = map.lights.iter().filter(|light| light.dimension.contains(dimension)).take(MAX_LIGHTS).collect();
+    for (index, light) in shining.iter().enumerate() {
+        let (x, y) = (light.x as f32, light.y as f32);
+        // Two waves at odd rates so nearby lights don't flicker together
+        let wave = (time * 11. + x * 1.7).sin() * (time * 4.3 + y * 2.9).sin();
+        let brightness = 1. - light.flicker * (0.5 + 0.5 * wave);
+        lights[index] = Vec4::new(x, y, light.radius, brightness);
+        let [r, g, b] = light.color;
+        colors[index] = Vec4::new(r, g, b, 1.);
+    }
+    (lights, colors, shining.len() as u32)
 }
 
 pub fn init_dimension_world(
@@ -127,24 +125,30 @@ pub fn init_dimension_world(
     let image_handle = dimension_handle.get_image_handle(dimension);
     let render_layer = dimension_handle.get_render_layer(dimension);
     let (front_color, other_color) = dimension_handle.get_color(dimension);
-    // Spawn the light camera
+    // Spawn the dimension camera
     let mut camera = Camera2dBundle::default();
     camera.camera.target = RenderTarget::Image(image_handle);
     camera.camera_2d.clear_color = ClearColorConfig::Custom(Color::rgba(0., 0., 0., 0.));
     commands.spawn((camera, render_layer)).insert(GameEntity);
 
-    let (dimension_cells, other_cells) = match dimension {
-        Dimension::Light => (&mut game_data.map.light_cells, &mut game_data.map.dark_cells),
-        Dimension::Dark => (&mut game_data.map.dark_cells, &mut game_data.map.light_cells)
-    };
-
-    for cell in dimension_cells {
+    for cell in &game_data.map.cells[dimension.index()] {
         let position = Vec2::new(cell.x, cell.y);
+        if let Some(terrain) = cell.terrain {
+            spawn_terrain(
+                commands,
+                size_data,
+                materials,
+                meshes,
+                render_layer,
+                position,
+                terrain
+            );
+        }
         match &cell.item_type {
             ItemType::Wall => {
                 spawn_quad(
                     commands,
-                    &size_data,
+                    size_data,
                     front_color,
                     materials,
                     meshes,
@@ -155,30 +159,127 @@ pub fn init_dimension_world(
             ItemType::Door(d) => {
                 spawn_door(
                     commands,
-                    &size_data,
+                    size_data,
                     front_color,
                     materials,
                     meshes,
                     render_layer,
                     position,
-                    d
+                    dimension
                 );
+                if d.timer > 0. {
+                    spawn_door_countdown(
+                        commands,
+                        size_data,
+                        materials,
+                        meshes,
+                        render_layer,
+                        position,
+                        dimension
+                    );
+                }
             },
             ItemType::Key(k) => {
                 spawn_key(
                     commands,
-                    &size_data,
+                    size_data,
                     materials,
                     meshes,
                     render_layer,
                     position,
+                    dimension,
                     k
                 );
             },
+            ItemType::Portal(p) => {
+                // A cross dimension portal shows the color of the dimension it leads to
+                let exit_color = p.exit_dimension
+                    .filter(|exit| *exit != dimension)
+                    .map(|exit| dimension_handle.get_color(exit).0);
+                spawn_portal(
+                    commands,
+                    size_data,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    exit_color,
+                );
+            },
+            ItemType::Gate(g) => {
+                spawn_gate(
+                    commands,
+                    size_data,
+                    front_color,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    &game_data.map,
+                    g
+                );
+            },
+            ItemType::Switch(s) => {
+                spawn_switch(
+                    commands,
+                    size_data,
+                    front_color,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    s
+                );
+            },
+            ItemType::OneWay(o) => {
+                spawn_one_way(
+                    commands,
+                    size_data,
+                    front_color,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    dimension,
+                    o
+                );
+            },
+            ItemType::Collectible(_) => {
+                spawn_collectible(
+                    commands,
+                    size_data,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    dimension
+                );
+            },
+            ItemType::EnergyPickup(_) => {
+                spawn_energy_pickup(
+                    commands,
+                    size_data,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    dimension
+                );
+            },
+            ItemType::Checkpoint => {
+                spawn_checkpoint(
+                    commands,
+                    size_data,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                );
+            },
             ItemType::Goal => {
                 spawn_goal(
                     commands,
-                    &size_data,
+                    size_data,
                     materials,
                     meshes,
                     render_layer,
@@ -190,14 +291,18 @@ pub fn init_dimension_world(
         }
     }
 
-    
-    for cell in other_cells {
+    // Walls of every other dimension are shown faded
+    let other_cells = game_data.map.cells.iter()
+        .enumerate()
+        .filter(|(index, _)| *index != dimension.index())
+        .flat_map(|(index, cells)| cells.iter().map(move |cell| (Dimension(index as u8), cell)));
+    for (other_dimension, cell) in other_cells {
         let position = Vec2::new(cell.x, cell.y);
         match &cell.item_type {
             ItemType::Wall => {
                 spawn_quad(
                     commands,
-                    &size_data,
+                    size_data,
                     other_color,
                     materials,
                     meshes,
@@ -205,22 +310,124 @@ pub fn init_dimension_world(
                     position,
                 );
             }
-            ItemType::Door(d) => {
+            ItemType::Door(_) => {
                 spawn_door(
                     commands,
-                    &size_data,
+                    size_data,
+                    other_color,
+                    materials,
+                    meshes,
+                    render_layer,
+                    position,
+                    other_dimension
+                );
+            },
+            ItemType::Gate(g) => {
+                spawn_gate(
+                    commands,
+                    size_data,
                     other_color,
                     materials,
                     meshes,
                     render_layer,
                     position,
-                    d
+                    &game_data.map,
+                    g
                 );
             },
             _ => ()
         }
     }
 
+    for zone in &game_data.map.zones {
+        let color = match zone.kind {
+            ZoneKind::Block => Color::rgba(0.9, 0.25, 0.25, 0.2),
+            ZoneKind::Force(target) => dimension_handle.get_color(target).0.with_a(0.2),
+            ZoneKind::Flip { .. } => Color::rgba(0.6, 0.3, 0.9, 0.2),
+        };
+        spawn_zone(
+            commands,
+            size_data,
+            color,
+            materials,
+            meshes,
+            render_layer,
+            zone
+        );
+    }
+
+    for (index, moving_wall) in game_data.map.moving_walls.iter().enumerate() {
+        let color = if moving_wall.path.dimension.contains(dimension) { front_color } else { other_color };
+        spawn_moving_wall(
+            commands,
+            size_data,
+            color,
+            materials,
+            meshes,
+            render_layer,
+            Vec2::new(moving_wall.x, moving_wall.y),
+            index
+        );
+    }
+
+    // Like walls, blocks of the other dimensions are shown faded
+    for (index, block) in game_data.map.blocks.iter().enumerate() {
+        let color = if block.dimension.contains(dimension) { front_color } else { other_color };
+        spawn_block(
+            commands,
+            size_data,
+            color,
+            materials,
+            meshes,
+            render_layer,
+            Vec2::new(block.x as f32, block.y as f32),
+            index
+        );
+    }
+
+    for sign in &game_data.map.signs {
+        if sign.dimension.contains(dimension) {
+            spawn_sign(
+                commands,
+                size_data,
+                materials,
+                meshes,
+                render_layer,
+                sign
+            );
+        }
+    }
+
+    // Hazards only matter in their own dimension
+    for (index, hazard) in game_data.map.hazards.iter().enumerate() {
+        if hazard.dimension == dimension {
+            spawn_hazard(
+                commands,
+                size_data,
+                materials,
+                meshes,
+                render_layer,
+                hazard,
+                index
+            );
+        }
+    }
+
+    // Sentries can't be seen from the other dimensions
+    for (index, sentry) in game_data.map.sentries.iter().enumerate() {
+        if sentry.data.dimension == dimension {
+            spawn_sentry(
+                commands,
+                size_data,
+                materials,
+                meshes,
+                render_layer,
+                Vec2::new(sentry.x, sentry.y),
+                index
+            );
+        }
+    }
+
     // Spawn the player
     spawn_player(
         commands,
@@ -233,6 +440,34 @@ pub fn init_dimension_world(
     );
 }
 
+pub(crate) fn switch_dimension(
+    game_data: &mut GameData,
+    target: Dimension,
+    dimension: &DimensionHandle,
+    texture_query: &mut Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
+    camera_query: &mut Query<&mut Camera2d, With<FullScreen>>,    
+) {
+    game_data.dimension = target;
+    show_dimension(target, dimension, texture_query, camera_query);
+}
+
+// Only changes what is on screen, the player stays in its dimension
+pub(crate) fn show_dimension(
+    target: Dimension,
+    dimension: &DimensionHandle,
+    texture_query: &mut Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
+    camera_query: &mut Query<&mut Camera2d, With<FullScreen>>,
+) {
+    let mew_shader_handle = dimension.get_shader_handle(target);
+    let mew_clear_color = dimension.get_clear_color(target);
+    for mut material_handle in texture_query.iter_mut() {
+        *material_handle = mew_shader_handle.clone();
+    }
+    for mut camera in camera_query.iter_mut() {
+        camera.clear_color = ClearColorConfig::Custom(mew_clear_color);
+    }
+}
+
 fn spawn_quad(
     commands: &mut Commands,
     size_date: &SizeDate,
@@ -260,6 +495,186 @@ fn spawn_quad(
         .insert(layer).insert(GameEntity);
 }
 
+#[allow(clippy::too_many_arguments)]
+fn spawn_moving_wall(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    color: Color,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    index: usize
+) {
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width,
+                size_date.quad_height,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(MovingWallId(index));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_block(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    color: Color,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    index: usize
+) {
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    // A bit smaller than a wall so it reads as something that moves
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.8,
+                size_date.quad_height * 0.8,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(BlockId(index));
+}
+
+fn spawn_sign(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    sign: &Sign,
+) {
+    let color = Color::rgb(0.9, 0.65, 0.45);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(sign.x as f32);
+    let quad_y = size_date.get_world_y(sign.y as f32);
+
+    // A board on a post
+    let parts = [(-0.2, 0.1, 0.4), (0.15, 0.7, 0.4)];
+    for (offset, width, height) in parts {
+        commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+                transform: Transform::from_xyz(quad_x, quad_y + offset * size_date.quad_height, 0.).with_scale(Vec3::new(
+                    size_date.quad_width * width,
+                    size_date.quad_height * height,
+                    0.,
+                )),
+                material: materials.add(ColorMaterial::from(color)),
+                ..default()
+            })
+            .insert(layer)
+            .insert(GameEntity);
+    }
+}
+
+fn spawn_hazard(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    hazard: &Hazard,
+    index: usize
+) {
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(hazard.x as f32);
+    let quad_y = size_date.get_world_y(hazard.y as f32);
+
+    // The base always shows where the hazard is, on the floor below the items
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, -0.1).with_scale(Vec3::new(
+                size_date.quad_width * 0.9,
+                size_date.quad_height * 0.9,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(Color::rgba(0.8, 0.15, 0.2, 0.25))),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+
+    // Spikes are three triangles, a laser a thin beam across the tile
+    let color = Color::rgb(0.85, 0.15, 0.2);
+    let parts: Vec<(f32, f32, f32, Mesh)> = match hazard.kind {
+        HazardKind::Spikes => (-1..=1)
+            .map(|offset| (offset as f32 * 0.3, 0.3, 0.6, Mesh::from(shape::RegularPolygon::new(0.5, 3))))
+            .collect(),
+        HazardKind::Laser => vec![(0., 1., 0.15, Mesh::from(shape::Quad::default()))],
+    };
+    for (offset, width, height, mesh) in parts {
+        commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: meshes.add(mesh).into(),
+                transform: Transform::from_xyz(quad_x + offset * size_date.quad_width, quad_y, 0.).with_scale(Vec3::new(
+                    size_date.quad_width * width,
+                    size_date.quad_height * height,
+                    0.,
+                )),
+                material: materials.add(ColorMaterial::from(color)),
+                ..default()
+            })
+            .insert(layer)
+            .insert(GameEntity)
+            .insert(HazardItem { index });
+    }
+}
+
+fn spawn_sentry(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    index: usize
+) {
+    let color = Color::rgb(0.9, 0.2, 0.25);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Circle::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.2).with_scale(Vec3::new(
+                size_date.quad_width * 0.6,
+                size_date.quad_height * 0.6,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(SentryId(index));
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_door(
     commands: &mut Commands,
     size_date: &SizeDate,
@@ -268,7 +683,7 @@ fn spawn_door(
     meshes: &mut Assets<Mesh>,
     layer: RenderLayers,
     position: Vec2,
-    door: &Door
+    dimension: Dimension,
 ) {
     // Calculate the position of the quad relative to the window size
     let quad_x = size_date.get_world_x(position.x);
@@ -287,9 +702,42 @@ fn spawn_door(
         })
         .insert(layer)
         .insert(GameEntity)
-        .insert(DoorId(door.id));
+        .insert(DoorWall { x: position.x, y: position.y, dimension });
+}
+
+fn spawn_door_countdown(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    dimension: Dimension,
+) {
+    let color = Color::rgb(0.9, 0.75, 0.2);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    // A bar across the opened door, hidden while it is closed
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.1).with_scale(Vec3::new(
+                size_date.quad_width * 0.8,
+                size_date.quad_height * 0.15,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(DoorCountdown { x: position.x, y: position.y, dimension });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_key(
     commands: &mut Commands,
     size_date: &SizeDate,
@@ -297,9 +745,11 @@ fn spawn_key(
     meshes: &mut Assets<Mesh>,
     layer: RenderLayers,
     position: Vec2,
+    dimension: Dimension,
     key: &Key
 ) {
-    let color = Color::rgb(0.5, 0.5, 0.5);
+    // Generic keys are lighter
+    let color = if key.generic { Color::rgb(0.8, 0.8, 0.85) } else { Color::rgb(0.5, 0.5, 0.5) };
     // Calculate the position of the quad relative to the window size
     let quad_x = size_date.get_world_x(position.x);
     let quad_y = size_date.get_world_y(position.y);
@@ -317,7 +767,371 @@ fn spawn_key(
         })
         .insert(layer)
         .insert(GameEntity)
-        .insert(DoorId(key.door_id));
+        .insert(KeyItem { x: position.x, y: position.y, dimension });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_gate(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    color: Color,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    map: &Map,
+    gate: &Gate
+) {
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+    let visibility = if map.is_gate_open(gate) { Visibility::Hidden } else { Visibility::Inherited };
+
+    // Slightly smaller than a wall so gates can be told apart
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.9,
+                size_date.quad_height * 0.9,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            visibility,
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(GateWall(gate.clone()));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_switch(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    color: Color,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    switch: &Switch
+) {
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+    // A lever is a small block, a plate lies flat on the floor
+    let (scale_x, scale_y) = match switch.kind {
+        SwitchKind::Lever => (0.3, 0.5),
+        SwitchKind::Plate => (0.7, 0.25),
+    };
+
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * scale_x,
+                size_date.quad_height * scale_y,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+}
+
+fn spawn_terrain(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    terrain: TerrainKind
+) {
+    let color = match terrain {
+        TerrainKind::Ice => Color::rgba(0.6, 0.82, 0.95, 0.5),
+        TerrainKind::Mud => Color::rgba(0.47, 0.33, 0.2, 0.5),
+        TerrainKind::Conveyor(_) => Color::rgba(0.5, 0.5, 0.5, 0.5),
+    };
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    // Below every other item
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, -0.2).with_scale(Vec3::new(
+                size_date.quad_width,
+                size_date.quad_height,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+
+    if let TerrainKind::Conveyor(direction) = terrain {
+        commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::RegularPolygon::new(0.5, 3))).into(),
+                transform: Transform::from_xyz(quad_x, quad_y, -0.1)
+                    .with_rotation(Quat::from_rotation_z(direction_angle(direction)))
+                    .with_scale(Vec3::new(
+                        size_date.quad_width * 0.4,
+                        size_date.quad_height * 0.4,
+                        0.,
+                    )),
+                material: materials.add(ColorMaterial::from(Color::rgba(0.35, 0.35, 0.35, 0.8))),
+                ..default()
+            })
+            .insert(layer)
+            .insert(GameEntity);
+    }
+}
+
+// Tinted rectangle over the cells of a switch zone
+fn spawn_zone(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    color: Color,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    zone: &SwitchZone,
+) {
+    // Center of the rectangle of cells
+    let quad_x = size_date.get_world_x(zone.x as f32 + (zone.width - 1) as f32 / 2.);
+    let quad_y = size_date.get_world_y(zone.y as f32 + (zone.height - 1) as f32 / 2.);
+
+    // Below the terrains
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, -0.3).with_scale(Vec3::new(
+                size_date.quad_width * zone.width as f32,
+                size_date.quad_height * zone.height as f32,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+}
+
+// Rotation of a triangle pointing up, the world y axis goes up while the map one goes down
+fn direction_angle(direction: Direction) -> f32 {
+    match direction {
+        Direction::Up => 0.,
+        Direction::Left => std::f32::consts::FRAC_PI_2,
+        Direction::Down => std::f32::consts::PI,
+        Direction::Right => -std::f32::consts::FRAC_PI_2,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_one_way(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    color: Color,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    dimension: Dimension,
+    one_way: &OneWay
+) {
+    let arrow_color = Color::rgb(0.25, 0.55, 0.3);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::RegularPolygon::new(0.5, 3))).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.)
+                .with_rotation(Quat::from_rotation_z(direction_angle(one_way.direction)))
+                .with_scale(Vec3::new(
+                    size_date.quad_width * 0.6,
+                    size_date.quad_height * 0.6,
+                    0.,
+                )),
+            material: materials.add(ColorMaterial::from(arrow_color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+
+    // The wall a one-way door turns into, hidden until it closes
+    if one_way.kind == OneWayKind::Door {
+        commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+                transform: Transform::from_xyz(quad_x, quad_y, 0.1).with_scale(Vec3::new(
+                    size_date.quad_width,
+                    size_date.quad_height,
+                    0.,
+                )),
+                material: materials.add(ColorMaterial::from(color)),
+                visibility: Visibility::Hidden,
+                ..default()
+            })
+            .insert(layer)
+            .insert(GameEntity)
+            .insert(OneWayDoorWall { x: position.x, y: position.y, dimension });
+    }
+}
+
+fn spawn_portal(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    exit_color: Option<Color>,
+) {
+    let color = Color::rgb(0.7, 0.3, 0.55);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Circle::default())).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.9,
+                size_date.quad_height * 0.9,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+
+    if let Some(exit_color) = exit_color {
+        commands
+            .spawn(MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::Circle::default())).into(),
+                transform: Transform::from_xyz(quad_x, quad_y, 0.1).with_scale(Vec3::new(
+                    size_date.quad_width * 0.4,
+                    size_date.quad_height * 0.4,
+                    0.,
+                )),
+                material: materials.add(ColorMaterial::from(exit_color)),
+                ..default()
+            })
+            .insert(layer)
+            .insert(GameEntity);
+    }
+}
+
+fn spawn_collectible(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    dimension: Dimension,
+) {
+    let color = Color::rgb(0.55, 0.95, 0.9);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    // A small diamond
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::RegularPolygon::new(0.5, 4))).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.35,
+                size_date.quad_height * 0.5,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(CollectibleItem { x: position.x, y: position.y, dimension });
+}
+
+fn spawn_energy_pickup(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+    dimension: Dimension,
+) {
+    let color = Color::rgb(1., 0.9, 0.37);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    // A small triangle
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::RegularPolygon::new(0.5, 3))).into(),
+            transform: Transform::from_xyz(quad_x, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.5,
+                size_date.quad_height * 0.5,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity)
+        .insert(EnergyItem { x: position.x, y: position.y, dimension });
+}
+
+fn spawn_checkpoint(
+    commands: &mut Commands,
+    size_date: &SizeDate,
+    materials: &mut Assets<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: RenderLayers,
+    position: Vec2,
+) {
+    let color = Color::rgb(0.72, 0.43, 0.31);
+    // Calculate the position of the quad relative to the window size
+    let quad_x = size_date.get_world_x(position.x);
+    let quad_y = size_date.get_world_y(position.y);
+
+    // A flag, the pole then the cloth on its top right
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x - size_date.quad_width * 0.2, quad_y, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.1,
+                size_date.quad_height * 0.7,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            transform: Transform::from_xyz(quad_x + size_date.quad_width * 0.05, quad_y + size_date.quad_height * 0.2, 0.).with_scale(Vec3::new(
+                size_date.quad_width * 0.45,
+                size_date.quad_height * 0.3,
+                0.,
+            )),
+            material: materials.add(ColorMaterial::from(color)),
+            ..default()
+        })
+        .insert(layer)
+        .insert(GameEntity);
 }
 
 fn spawn_player(
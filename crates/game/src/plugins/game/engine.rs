@@ -1,14 +1,28 @@
-use super::map::Map;
-use map_shared::{Dimension, MapData};
-use bevy::prelude::Resource;
+use super::map::{Door, Key, Map, MapState};
+use map_shared::{AbilityData, Dimension, DimensionData, EnergyData, MapData, MovementData, Sign, TutorialEvent, ZoneKind};
+use crate::plugins::menu::plugin::Difficulty;
+use crate::map::progress::map_key;
+use bevy::{prelude::Resource, utils::{HashMap, HashSet}};
+
+// Same vision as the shader: always lit close around the player, then a cone in front of it
+const LIT_RADIUS: f32 = 1.5;
+const CONE_COSINE: f32 = 0.707;
 
 pub struct Player {
     pub x: f32,
     pub y: f32,
     pub dir_x: f32,
     pub dir_y: f32,
+    // Cells per second, without the conveyor drift
+    pub vel_x: f32,
+    pub vel_y: f32,
     pub goal_x: i32,
     pub goal_y: i32,
+    // Set when the player arrives through a portal, portals are ignored until it steps off
+    pub on_portal: bool,
+    // Position at the end of the last physic update, gives the movement of the frame
+    pub last_x: f32,
+    pub last_y: f32,
 }
 impl Player {
     fn new(level_data: &MapData) -> Player {
@@ -17,10 +31,125 @@ impl Player {
             y: level_data.start_y as f32 + 0.5,
             dir_x: 1.,
             dir_y: 0.,
+            vel_x: 0.,
+            vel_y: 0.,
             goal_x: level_data.goal_x,
             goal_y: level_data.goal_y,
+            on_portal: false,
+            last_x: level_data.start_x as f32 + 0.5,
+            last_y: level_data.start_y as f32 + 0.5,
+        }
+    }
+}
+
+// Keys picked up with the inventory key rule
+#[derive(Clone, Default)]
+pub struct Inventory {
+    // Count for each door id
+    pub keys: HashMap<u32, u32>,
+    pub generic: u32,
+}
+impl Inventory {
+    pub(crate) fn add(&mut self, key: &Key) {
+        if key.generic {
+            self.generic += 1;
+        } else {
+            *self.keys.entry(key.door_id).or_insert(0) += 1;
+        }
+    }
+
+    // Whether the door opens with the keys held, they are used up if the door consumes them
+    pub(crate) fn unlock(&mut self, door: &Door) -> bool {
+        let held = if door.generic { &mut self.generic } else { self.keys.entry(door.id).or_insert(0) };
+        if *held < door.keys_needed {
+            return false;
+        }
+        if door.consume {
+            *held -= door.keys_needed;
+        }
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct Energy {
+    pub data: EnergyData,
+    pub value: f32,
+}
+impl Energy {
+    fn new(data: EnergyData) -> Energy {
+        Energy { data, value: data.max }
+    }
+
+    pub(crate) fn refill(&mut self, amount: f32) {
+        self.value = (self.value + amount).min(self.data.max);
+    }
+}
+
+pub struct Ability {
+    pub data: AbilityData,
+    // Seconds before it is ready again
+    pub cooldown: f32,
+    // Seconds left on the running effect
+    pub active: f32,
+    pub used: u32,
+}
+impl Ability {
+    fn new(data: AbilityData) -> Ability {
+        Ability { data, cooldown: 0., active: 0., used: 0 }
+    }
+
+    // Uses left in the run, None without limit
+    pub(crate) fn uses_left(&self) -> Option<u32> {
+        (self.data.uses > 0).then(|| self.data.uses.saturating_sub(self.used))
+    }
+
+    pub(crate) fn ready(&self) -> bool {
+        self.data.enabled && self.cooldown <= 0. && self.uses_left() != Some(0)
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active > 0.
+    }
+
+    // Starts the effect for duration seconds if the ability is ready
+    pub(crate) fn trigger(&mut self, duration: f32) -> bool {
+        if !self.ready() {
+            return false;
         }
+        self.used += 1;
+        self.cooldown = self.data.cooldown;
+        self.active = duration;
+        true
     }
+
+    // Returns true on the update the effect ends
+    pub(crate) fn tick(&mut self, delta: f32) -> bool {
+        self.cooldown = (self.cooldown - delta).max(0.);
+        if self.active <= 0. {
+            return false;
+        }
+        self.active -= delta;
+        self.active <= 0.
+    }
+}
+
+pub struct Abilities {
+    pub peek: Ability,
+    pub sonar: Ability,
+    pub dash: Ability,
+}
+
+// Where the player comes back after losing a life, the start until a checkpoint is touched
+pub struct Checkpoint {
+    // Position of the checkpoint cell, None for the start
+    pub cell: Option<(f32, f32)>,
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+    pub map_state: MapState,
+    pub inventory: Inventory,
+    pub energy: Option<Energy>,
 }
 
 #[derive(Resource)]
@@ -56,29 +185,156 @@ impl SizeDate {
     }
 
     pub(crate) fn get_world_x(&self, x: f32) -> f32 {
-        let res = (x * self.quad_width) + self.trans_x;
-        return res;
+        (x * self.quad_width) + self.trans_x
     }
     pub(crate) fn get_world_y(&self, y: f32) -> f32 {
-        let res = (-y * self.quad_height) + self.trans_y;
-        return res;
+        (-y * self.quad_height) + self.trans_y
     }
 }
 
 #[derive(Resource)]
 pub struct GameData {
+    // Where the best completion of the map is saved
+    pub progress_key: String,
     pub map: Map,
     pub player: Player,
     pub dimension: Dimension,
+    pub dimensions: Vec<DimensionData>,
     pub dimension_enabled: bool,
+    pub movement: MovementData,
+    // Walls push the player back instead of killing, switching dimension into a wall still kills
+    pub forgiving_collisions: bool,
+    // Dimension at the end of the last physic update, tells if the player just switched
+    pub previous_dimension: Dimension,
+    pub lives: u32,
+    pub checkpoint: Checkpoint,
+    pub inventory: Inventory,
+    pub abilities: Abilities,
+    // None when switching is free
+    pub energy: Option<Energy>,
+    // Switch zone the player is in, and the time spent in it for flipping zones
+    pub zone: Option<usize>,
+    pub zone_clock: f32,
+    // What happened at least once in the run, for the tutorial steps
+    pub events: HashSet<TutorialEvent>,
 }
 impl GameData {
-    pub(crate) fn new(level_data: &MapData) -> GameData {
+    pub(crate) fn new(level_data: &MapData, difficulty: Difficulty) -> GameData {
+        let scale = difficulty.speed_scale();
+        let map = Map::new(level_data);
+        let player = Player::new(level_data);
+        let energy = level_data.energy.map(Energy::new);
+        let checkpoint = Checkpoint {
+            cell: None,
+            x: player.x,
+            y: player.y,
+            dimension: Dimension::LIGHT,
+            map_state: map.save_state(),
+            inventory: Inventory::default(),
+            energy: energy.clone(),
+        };
         GameData {
-            map: Map::new(level_data),
-            player: Player::new(level_data),
-            dimension: Dimension::Light,
+            progress_key: map_key(level_data),
+            map,
+            player,
+            dimension: Dimension::LIGHT,
+            dimensions: level_data.dimensions.clone(),
             dimension_enabled: true,
+            movement: MovementData {
+                max_speed: level_data.movement.max_speed * scale,
+                acceleration: level_data.movement.acceleration * scale,
+                friction: level_data.movement.friction * scale,
+            },
+            forgiving_collisions: level_data.forgiving_collisions || difficulty.forgiving_collisions(),
+            previous_dimension: Dimension::LIGHT,
+            lives: level_data.lives,
+            checkpoint,
+            inventory: Inventory::default(),
+            abilities: Abilities {
+                peek: Ability::new(level_data.abilities.peek),
+                sonar: Ability::new(level_data.abilities.sonar),
+                dash: Ability::new(level_data.abilities.dash),
+            },
+            energy,
+            zone: None,
+            zone_clock: 0.,
+            events: HashSet::new(),
         }
     }
+
+    pub(crate) fn save_checkpoint(&mut self, cell_x: f32, cell_y: f32) {
+        self.checkpoint = Checkpoint {
+            cell: Some((cell_x, cell_y)),
+            x: self.player.x,
+            y: self.player.y,
+            dimension: self.dimension,
+            map_state: self.map.save_state(),
+            inventory: self.inventory.clone(),
+            energy: self.energy.clone(),
+        };
+    }
+
+    // Put the player and the level back as they were at the last checkpoint, returns the dimension to show
+    pub(crate) fn respawn(&mut self) -> Dimension {
+        let checkpoint = &self.checkpoint;
+        self.map.restore_state(&checkpoint.map_state);
+        self.inventory = checkpoint.inventory.clone();
+        self.energy = checkpoint.energy.clone();
+        self.player.x = checkpoint.x;
+        self.player.y = checkpoint.y;
+        self.player.last_x = checkpoint.x;
+        self.player.last_y = checkpoint.y;
+        self.player.vel_x = 0.;
+        self.player.vel_y = 0.;
+        self.player.on_portal = false;
+        self.zone = None;
+        // Cooldowns and uses carry on, only the running effects stop
+        self.abilities.peek.active = 0.;
+        self.abilities.sonar.active = 0.;
+        self.abilities.dash.active = 0.;
+        checkpoint.dimension
+    }
+
+    // Closest sign of the current dimension the player is near and can see
+    pub(crate) fn visible_sign(&self) -> Option<&Sign> {
+        let player = &self.player;
+        self.map.signs.iter()
+            .filter(|sign| sign.dimension.contains(self.dimension))
+            .map(|sign| {
+                let (dx, dy) = (sign.x as f32 - player.x, sign.y as f32 - player.y);
+                (sign, dx, dy, (dx * dx + dy * dy).sqrt())
+            })
+            .filter(|(sign, dx, dy, distance)| {
+                // Standing on the sign sees it, there is no direction to normalise
+                let in_cone = *distance == 0. || (dx * player.dir_x + dy * player.dir_y) / distance > CONE_COSINE;
+                *distance <= sign.radius && (*distance <= LIT_RADIUS || in_cone)
+            })
+            .min_by(|a, b| a.3.total_cmp(&b.3))
+            .map(|(sign, ..)| sign)
+    }
+
+    pub(crate) fn zone_kind(&self) -> Option<ZoneKind> {
+        self.zone.and_then(|index| self.map.zones.get(index)).map(|zone| zone.kind)
+    }
+
+    // Blocking and forcing zones keep the player in its dimension
+    pub(crate) fn can_switch(&self) -> bool {
+        self.dimension_enabled && !matches!(self.zone_kind(), Some(ZoneKind::Block | ZoneKind::Force(_)))
+    }
+
+    // Pays for a switch, always possible when switching is free
+    pub(crate) fn spend_switch_energy(&mut self) -> bool {
+        match &mut self.energy {
+            Some(energy) if energy.value < energy.data.cost => false,
+            Some(energy) => {
+                energy.value -= energy.data.cost;
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn dimension_count(&self) -> usize {
+        self.dimensions.len()
+    }
 }
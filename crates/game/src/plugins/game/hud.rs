@@ -0,0 +1,83 @@
+use bevy::prelude::Res;
+use bevy_egui::{egui, EguiContexts};
+use map_shared::{KeyRule, ZoneKind};
+
+use super::{engine::{Ability, GameData}, tutorial::Tutorial};
+
+// Small overlay in the top left corner with the state of the run
+pub fn hud_system(
+    game_data: Res<GameData>,
+    mut contexts: EguiContexts,
+) {
+    let ctx = contexts.ctx_mut();
+
+    egui::Area::new("hud")
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(10., 10.))
+        .show(ctx, |ui| {
+            ui.label(format!("Lives: {}", game_data.lives));
+            if let Some(energy) = &game_data.energy {
+                let bar = egui::ProgressBar::new(energy.value / energy.data.max)
+                    .desired_width(300.)
+                    .text(format!("Energy {:.0}/{:.0}", energy.value, energy.data.max));
+                ui.add(bar);
+            }
+            if game_data.map.collectible_total > 0 {
+                ui.label(format!("Shards: {}/{}", game_data.map.collected(), game_data.map.collectible_total));
+            }
+            if game_data.map.key_rule == KeyRule::Inventory {
+                let inventory = &game_data.inventory;
+                let mut keys: Vec<_> = inventory.keys.iter().filter(|(_, count)| **count > 0).collect();
+                keys.sort();
+                for (door_id, count) in keys {
+                    ui.label(format!("Key {}: {}", door_id, count));
+                }
+                if inventory.generic > 0 {
+                    ui.label(format!("Generic keys: {}", inventory.generic));
+                }
+            }
+            match game_data.zone_kind() {
+                Some(ZoneKind::Block) => { ui.label("Switching blocked"); },
+                Some(ZoneKind::Force(_)) => { ui.label("Dimension locked"); },
+                Some(ZoneKind::Flip { period }) => { ui.label(format!("Flip in {:.1}s", period - game_data.zone_clock)); },
+                None => (),
+            }
+            let abilities = &game_data.abilities;
+            for (name, ability) in [("Peek", &abilities.peek), ("Sonar", &abilities.sonar), ("Dash", &abilities.dash)] {
+                if ability.data.enabled {
+                    ui.label(ability_label(name, ability));
+                }
+            }
+        });
+}
+
+// Text of the sign in view, the tutorial messages go first
+pub fn sign_system(
+    game_data: Res<GameData>,
+    tutorial: Res<Tutorial>,
+    mut contexts: EguiContexts,
+) {
+    if tutorial.is_showing() {
+        return;
+    }
+    if let Some(sign) = game_data.visible_sign() {
+        let ctx = contexts.ctx_mut();
+
+        egui::TopBottomPanel::bottom("sign_text_panel").show(ctx, |ui| {
+            ui.label(sign.text.as_str());
+        });
+    }
+}
+
+fn ability_label(name: &str, ability: &Ability) -> String {
+    let state = if ability.uses_left() == Some(0) {
+        "used up".to_string()
+    } else if ability.cooldown > 0. {
+        format!("{:.1}s", ability.cooldown)
+    } else {
+        "ready".to_string()
+    };
+    match ability.uses_left() {
+        Some(uses) if uses > 0 => format!("{}: {} ({} left)", name, state, uses),
+        _ => format!("{}: {}", name, state),
+    }
+}
@@ -1,20 +1,37 @@
 use bevy::{
     prelude::{GamepadAxisType, GamepadButtonType, KeyCode, Query, Res, ResMut, Transform, With, Handle, Camera2d, Assets, Vec2},
-    time::Time, core_pipeline::clear_color::ClearColorConfig,
+    time::Time,
 };
 
 use crate::{plugins::input::types::{Action, InputData, InputMap}};
-use map_shared::Dimension;
+use map_shared::{Dimension, TerrainKind, TutorialEvent};
 
-use super::{systems::{PlayerPosition, FullScreen}, engine::{GameData, SizeDate}, dimension::DimensionHandle, tutorial::Tutorial, shader::DimensionMaterial};
+use super::{ability::DASH_SPEED, systems::{PlayerPosition, FullScreen}, engine::{GameData, SizeDate}, dimension::{switch_dimension, DimensionHandle}, tutorial::Tutorial, shader::DimensionMaterial};
+
+// Part of the max speed reached in mud
+const MUD_SPEED: f32 = 0.4;
+// Cells per second
+const CONVEYOR_SPEED: f32 = 1.5;
+// Part of the acceleration and friction left on ice
+const ICE_GRIP: f32 = 0.1;
 
 pub fn setup_input(mut input_map: ResMut<InputMap>) {
     // bind keyboard keys
     input_map.keyboard_map.insert(KeyCode::Space, Action::ButtonA);
+    input_map.keyboard_map.insert(KeyCode::Z, Action::ButtonB);
+    input_map.keyboard_map.insert(KeyCode::X, Action::ButtonX);
+    input_map.keyboard_map.insert(KeyCode::C, Action::ButtonY);
     input_map.keyboard_map.insert(KeyCode::Up, Action::LeftStickY(-1.0));
     input_map.keyboard_map.insert(KeyCode::Down, Action::LeftStickY(1.0));
     input_map.keyboard_map.insert(KeyCode::Left, Action::LeftStickX(-1.0));
     input_map.keyboard_map.insert(KeyCode::Right, Action::LeftStickX(1.0));
+    let dimension_keys = [
+        KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+        KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    ];
+    for (index, key) in dimension_keys.into_iter().enumerate() {
+        input_map.keyboard_map.insert(key, Action::SelectDimension(index));
+    }
 
     // bind gamepad axis and buttons
     input_map.gamepad_axis_map.insert(GamepadAxisType::LeftStickX, Action::LeftStickX(1.0));
@@ -23,8 +40,13 @@ pub fn setup_input(mut input_map: ResMut<InputMap>) {
     input_map.gamepad_button_map.insert(GamepadButtonType::East, Action::ButtonB);
     input_map.gamepad_button_map.insert(GamepadButtonType::West, Action::ButtonX);
     input_map.gamepad_button_map.insert(GamepadButtonType::North, Action::ButtonY);
+    input_map.gamepad_button_map.insert(GamepadButtonType::DPadUp, Action::SelectDimension(0));
+    input_map.gamepad_button_map.insert(GamepadButtonType::DPadRight, Action::SelectDimension(1));
+    input_map.gamepad_button_map.insert(GamepadButtonType::DPadDown, Action::SelectDimension(2));
+    input_map.gamepad_button_map.insert(GamepadButtonType::DPadLeft, Action::SelectDimension(3));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn move_system(
     time: Res<Time>,
     size_date: Res<SizeDate>,
@@ -37,24 +59,57 @@ pub fn move_system(
     mut texture_query: Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
     mut camera_query: Query<&mut Camera2d, With<FullScreen>>,   
 ) {
-    tutorial.check_message(&mut game_data);
+    tutorial.check_message(&mut game_data, time.delta_seconds());
     if tutorial.current_message_index.is_some() {        
+        // The player stands still while reading
+        game_data.player.vel_x = 0.;
+        game_data.player.vel_y = 0.;
         if input_data.button_a {
-            tutorial.delete_message();
+            tutorial.acknowledge();
         } 
     } else {
         let dir_x = input_data.left_stick_x;
         let dir_y = input_data.left_stick_y;
 
-        let move_x = dir_x * time.delta_seconds();
-        let move_y = dir_y * time.delta_seconds();
-        if (dir_x > 0.01 || dir_x < -0.01) || (dir_y > 0.01 || dir_y < -0.01) {
+        // The input is already in the unit circle with the stick deadzone applied
+        if dir_x != 0. || dir_y != 0. {
             let dir_length = (dir_x * dir_x + dir_y * dir_y).sqrt();
             game_data.player.dir_x = dir_x/dir_length;
             game_data.player.dir_y = dir_y/dir_length;
         }
-        game_data.player.x += move_x * 2.;
-        game_data.player.y += move_y * 2.;
+
+        // The floor under the player changes how it moves
+        let delta = time.delta_seconds();
+        let terrain = game_data.map.terrain_at(game_data.player.x, game_data.player.y, game_data.dimension);
+        let movement = game_data.movement;
+        let (max_speed, grip, drift) = match terrain {
+            Some(TerrainKind::Ice) => (movement.max_speed, ICE_GRIP, [0., 0.]),
+            Some(TerrainKind::Mud) => (movement.max_speed * MUD_SPEED, 1., [0., 0.]),
+            Some(TerrainKind::Conveyor(direction)) => (movement.max_speed, 1., direction.vector().map(|d| d * CONVEYOR_SPEED)),
+            None => (movement.max_speed, 1., [0., 0.]),
+        };
+
+        // Accelerate towards the stick velocity, or slow down with friction once released
+        let pushed = dir_x != 0. || dir_y != 0.;
+        let rate = (if pushed { movement.acceleration } else { movement.friction }) * grip;
+        let dashing = game_data.abilities.dash.is_active();
+        let player = &mut game_data.player;
+        let (change_x, change_y) = (dir_x * max_speed - player.vel_x, dir_y * max_speed - player.vel_y);
+        let change = (change_x * change_x + change_y * change_y).sqrt();
+        let step = rate * delta;
+        if dashing {
+            // The dash goes straight where the player faces
+            player.vel_x = player.dir_x * max_speed * DASH_SPEED;
+            player.vel_y = player.dir_y * max_speed * DASH_SPEED;
+        } else if change <= step {
+            player.vel_x += change_x;
+            player.vel_y += change_y;
+        } else {
+            player.vel_x += change_x / change * step;
+            player.vel_y += change_y / change * step;
+        }
+        player.x += (player.vel_x + drift[0]) * delta;
+        player.y += (player.vel_y + drift[1]) * delta;
         let world_x = size_date.get_world_x(game_data.player.x);
         let world_y = size_date.get_world_y(game_data.player.y);
             
@@ -62,21 +117,35 @@ pub fn move_system(
             transform.translation.x = world_x;
             transform.translation.y = world_y;
         }
-        let light_handle = dimension.get_shader_handle(Dimension::Light);
-        let dark_handle = dimension.get_shader_handle(Dimension::Dark);
-
-        let light_material = materials.get_mut(&light_handle).unwrap();
-        light_material.shader_data.player_position = Vec2::new(game_data.player.x, game_data.player.y);
-        light_material.shader_data.player_direction = Vec2::new(game_data.player.dir_x, game_data.player.dir_y);
-
-        let dark_material = materials.get_mut(&dark_handle).unwrap();
-        dark_material.shader_data.player_position = Vec2::new(game_data.player.x, game_data.player.y);
-        dark_material.shader_data.player_direction = Vec2::new(game_data.player.dir_x, game_data.player.dir_y);
+        for handle in dimension.get_shader_handles() {
+            let material = materials.get_mut(handle).unwrap();
+            material.shader_data.player_position = Vec2::new(game_data.player.x, game_data.player.y);
+            material.shader_data.player_direction = Vec2::new(game_data.player.dir_x, game_data.player.dir_y);
+        }
 
+        // Button A cycles through the dimensions, the select actions jump to one
+        let mut target = None;
         if input_data.button_a {
-            if game_data.dimension_enabled {
+            let mut next = game_data.dimension;
+            next.switch_dimension(game_data.dimension_count());
+            target = Some(next);
+        }
+        if let Some(index) = input_data.select_dimension {
+            if index < game_data.dimension_count() {
+                target = Some(Dimension(index as u8));
+            }
+        }
+
+        if let Some(energy) = &mut game_data.energy {
+            let regen = energy.data.regen * delta;
+            energy.refill(regen);
+        }
+        if let Some(target) = target {
+            if game_data.can_switch() && target != game_data.dimension && game_data.spend_switch_energy() {
+                game_data.events.insert(TutorialEvent::FirstSwitch);
                 switch_dimension(
                     &mut game_data, 
+                    target,
                     &dimension, 
                     &mut texture_query,
                     &mut camera_query
@@ -85,21 +154,3 @@ pub fn move_system(
         }
     }
 }
-
-fn switch_dimension(
-    game_data: &mut GameData,
-    dimension: &DimensionHandle,
-    texture_query: &mut Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
-    camera_query: &mut Query<&mut Camera2d, With<FullScreen>>,    
-) {
-    game_data.dimension.switch_dimension();
-
-    let mew_shader_handle = dimension.get_shader_handle(game_data.dimension);
-    let mew_clear_color = dimension.get_clear_color(game_data.dimension);
-    for mut material_handle in texture_query.iter_mut() {
-        *material_handle = mew_shader_handle.clone();
-    }
-    for mut camera in camera_query.iter_mut() {
-        camera.clear_color = ClearColorConfig::Custom(mew_clear_color);
-    }
-}
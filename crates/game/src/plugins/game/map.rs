@@ -1,13 +1,20 @@
-use bevy::prelude::{warn, Entity, Commands, Query};
-use map_shared::{Dimension, MapData};
+use bevy::{prelude::{warn, Entity}, utils::HashSet};
+use map_shared::{path_position, Block, Dimension, Direction, Hazard, KeyRule, Light, Sign, MapData, OneWayKind, SwitchKind, SwitchZone, TerrainKind};
 
-use super::systems::DoorId;
+use super::physic::check_wall_collision;
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Door {
     pub open: bool,
     pub id: u32,
     pub entity: Option<Entity>,
+    // Seconds the door stays open, 0 for ever
+    pub timer: f32,
+    // Seconds left before it closes again
+    pub remaining: f32,
+    pub keys_needed: u32,
+    pub generic: bool,
+    pub consume: bool,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -15,14 +22,66 @@ pub struct Key {
     pub taken: bool,
     pub door_id: u32,
     pub entity: Option<Entity>,
+    pub generic: bool,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Portal {
+    pub id: u32,
+    pub exit_id: u32,
+    pub exit_dimension: Option<Dimension>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Gate {
+    pub group: u32,
+    // The gate is open while its group is inactive
+    pub open_by_default: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Switch {
+    pub group: u32,
+    pub kind: SwitchKind,
+    pub on: bool,
+    // The player stood on it last frame
+    pub pressed: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct OneWay {
+    pub direction: Direction,
+    pub kind: OneWayKind,
+    // The player is on the tile
+    pub crossing: bool,
+    // A one-way door the player went through
+    pub closed: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Collectible {
+    pub taken: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EnergyPickup {
+    pub amount: f32,
+    pub taken: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum ItemType {
     Goal,
     Wall,
     Door(Door),
     Key(Key),
+    Portal(Portal),
+    Gate(Gate),
+    Switch(Switch),
+    OneWay(OneWay),
+    Checkpoint,
+    Collectible(Collectible),
+    EnergyPickup(EnergyPickup),
     None,
 }
 
@@ -30,14 +89,17 @@ pub enum ItemType {
 pub struct Cell {
     pub x: f32,
     pub y: f32,
-    pub item_type: ItemType
+    pub item_type: ItemType,
+    // Floor under the item
+    pub terrain: Option<TerrainKind>,
 }
 impl Cell {
     pub fn new(x: i32, y: i32) -> Cell {
         Cell {
             x: x as f32,
             y: y as f32,
-            item_type: ItemType::None
+            item_type: ItemType::None,
+            terrain: None,
         }
     }
 
@@ -49,31 +111,97 @@ impl Cell {
     }
 }
 
+// Seconds a hazard is shown as about to turn on
+const HAZARD_WARNING: f32 = 0.6;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum HazardState {
+    Off,
+    Warning,
+    On,
+}
+
+pub struct MovingWall {
+    pub path: map_shared::MovingWall,
+    pub x: f32,
+    pub y: f32,
+}
+
+// Sentries move with a fixed step so a run only depends on the player, not on the frame rate
+pub const SENTRY_STEP: f32 = 1. / 60.;
+
+pub struct Sentry {
+    pub data: map_shared::Sentry,
+    pub x: f32,
+    pub y: f32,
+    // Distance travelled along the patrol path
+    pub travelled: f32,
+    // Chasing the player or walking back to the patrol path
+    pub chasing: bool,
+}
+
+// Everything a checkpoint brings back: doors, keys, switches, one-way doors, collectibles, energy pickups and blocks
+#[derive(Clone)]
+pub struct MapState {
+    cells: Vec<Vec<Cell>>,
+    active_groups: HashSet<u32>,
+    blocks: Vec<Block>,
+}
+
 pub struct Map {
     pub width: i32,
     pub height: i32,
-    pub light_cells: Vec<Cell>,
-    pub dark_cells: Vec<Cell>,
+    // One grid of cells per dimension
+    pub cells: Vec<Vec<Cell>>,
+    pub key_rule: KeyRule,
+    // Collectibles of the map, one in several dimensions counts once
+    pub collectible_total: usize,
+    // Groups with at least one switch on, their gates are toggled
+    pub active_groups: HashSet<u32>,
+    pub moving_walls: Vec<MovingWall>,
+    // Time the moving walls have been running for
+    pub time: f32,
+    pub sentries: Vec<Sentry>,
+    // Time not yet consumed by a sentry step
+    sentry_clock: f32,
+    pub zones: Vec<SwitchZone>,
+    pub lights: Vec<Light>,
+    // Blocks move on the grid so they are not cells
+    pub blocks: Vec<Block>,
+    // They run on the moving walls clock
+    pub hazards: Vec<Hazard>,
+    pub signs: Vec<Sign>,
 }
 
 impl Map {
     pub fn new(map_data: &MapData) -> Self {
         let height = (map_data.size / 16) * 9;
-        let light_cells = (0..map_data.size)
-            .flat_map(|x| (0..height).map(move |y| Cell::new(x, y)))
-            .collect();
-        let dark_cells = (0..map_data.size)
-            .flat_map(|x| (0..height).map(move |y| Cell::new(x, y)))
+        let cells = map_data.dimensions.iter()
+            .map(|_| (0..map_data.size)
+                .flat_map(|x| (0..height).map(move |y| Cell::new(x, y)))
+                .collect())
             .collect();
 
         let mut map = Map {
             width: map_data.size,
-            height: height,
-            light_cells,
-            dark_cells
+            height,
+            cells,
+            key_rule: map_data.key_rule,
+            collectible_total: 0,
+            active_groups: HashSet::new(),
+            moving_walls: Vec::new(),
+            time: 0.,
+            sentries: Vec::new(),
+            sentry_clock: 0.,
+            zones: map_data.switch_zones.clone(),
+            lights: map_data.lights.clone(),
+            blocks: map_data.blocks.clone(),
+            hazards: map_data.hazards.clone(),
+            signs: map_data.signs.clone(),
         };
 
         generate_map(map_data, &mut map);
+        map.collectible_total = map.collectible_positions(false).len();
         map
     }
 
@@ -82,14 +210,7 @@ impl Map {
         if x >= 0 && x < self.width && y >= 0 && y < self.height {
             // Calculate the index based on the provided x and y coordinates
             let index = (x * self.height + y) as usize;
-            match dimension {
-                Dimension::Light => {
-                    Some(self.light_cells[index].clone())
-                },
-                Dimension::Dark => {
-                    Some(self.dark_cells[index].clone())
-                },
-            }
+            self.cells.get(dimension.index()).map(|cells| cells[index].clone())
         } else {
             // Return None for out-of-bounds coordinates or special cases like (-1, -1)
             None
@@ -97,26 +218,242 @@ impl Map {
     }
 
     pub fn get_mut_cell(&mut self, x: i32, y: i32, dimension: Dimension) -> Option<&mut Cell> {
-        match dimension {
-            Dimension::Light => {
-                self.light_cells.get_mut((x * self.height + y) as usize)
-            },
-            Dimension::Dark => {
-                self.dark_cells.get_mut((x * self.height + y) as usize)
+        let index = (x * self.height + y) as usize;
+        self.cells.get_mut(dimension.index()).and_then(|cells| cells.get_mut(index))
+    }
+
+    // Position of the portal the player comes out of, the same in every dimension it exists in
+    pub(crate) fn portal_exit(&self, exit_id: u32) -> Option<(f32, f32)> {
+        self.cells.iter().flatten().find_map(|cell| match &cell.item_type {
+            ItemType::Portal(portal) if portal.id == exit_id => Some((cell.x, cell.y)),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn advance_moving_walls(&mut self, delta: f32) {
+        self.time += delta;
+        for moving_wall in &mut self.moving_walls {
+            [moving_wall.x, moving_wall.y] = moving_wall.path.position_at(self.time);
+        }
+    }
+
+    pub(crate) fn advance_sentries(&mut self, delta: f32, player_x: f32, player_y: f32, dimension: Dimension) {
+        self.sentry_clock += delta;
+        while self.sentry_clock >= SENTRY_STEP {
+            self.sentry_clock -= SENTRY_STEP;
+            for index in 0..self.sentries.len() {
+                self.step_sentry(index, player_x, player_y, dimension);
+            }
+        }
+    }
+
+    fn step_sentry(&mut self, index: usize, player_x: f32, player_y: f32, dimension: Dimension) {
+        let sentry = &self.sentries[index];
+        let step = sentry.data.speed * SENTRY_STEP;
+        let player_distance = ((player_x - sentry.x).powi(2) + (player_y - sentry.y).powi(2)).sqrt();
+        // The player is only seen from inside the sentry dimension
+        let sees_player = sentry.data.dimension == dimension
+            && player_distance <= sentry.data.chase_radius
+            && !self.is_view_blocked(sentry.x, sentry.y, player_x, player_y, dimension);
+
+        let (x, y, travelled, chasing) = if sees_player {
+            let (x, y) = self.move_towards(sentry, player_x, player_y, step, true);
+            (x, y, sentry.travelled, true)
+        } else if sentry.chasing {
+            let [path_x, path_y] = path_position(&sentry.data.path, sentry.data.mode, sentry.travelled);
+            let (x, y) = self.move_towards(sentry, path_x, path_y, step, false);
+            let back = (x - path_x).abs() < 1e-4 && (y - path_y).abs() < 1e-4;
+            (x, y, sentry.travelled, !back)
+        } else {
+            let travelled = sentry.travelled + step;
+            let [x, y] = path_position(&sentry.data.path, sentry.data.mode, travelled);
+            (x, y, travelled, false)
+        };
+
+        let sentry = &mut self.sentries[index];
+        (sentry.x, sentry.y, sentry.travelled, sentry.chasing) = (x, y, travelled, chasing);
+    }
+
+    /*
+        Walk straight to the target, one axis after the other so the sentry slides along walls
+        A sentry blocked on its way back to its path walks through the walls rather than staying stuck
+    */
+    fn move_towards(&self, sentry: &Sentry, target_x: f32, target_y: f32, step: f32, blocked_by_walls: bool) -> (f32, f32) {
+        let (dx, dy) = (target_x - sentry.x, target_y - sentry.y);
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance <= step {
+            return (target_x, target_y);
+        }
+        let (next_x, next_y) = (sentry.x + dx / distance * step, sentry.y + dy / distance * step);
+        if !blocked_by_walls {
+            return (next_x, next_y);
+        }
+
+        let dimension = sentry.data.dimension;
+        let x = if self.is_blocked(next_x, sentry.y, dimension) { sentry.x } else { next_x };
+        let y = if self.is_blocked(x, next_y, dimension) { sentry.y } else { next_y };
+        (x, y)
+    }
+
+    pub(crate) fn hazard_state(&self, hazard: &Hazard) -> HazardState {
+        let cycle = hazard.cycle_time(self.time);
+        if cycle < hazard.active {
+            HazardState::On
+        } else if hazard.period - cycle <= HAZARD_WARNING {
+            HazardState::Warning
+        } else {
+            HazardState::Off
+        }
+    }
+
+    // Whether a solid cell or a block stands on the straight line between two points
+    fn is_view_blocked(&self, from_x: f32, from_y: f32, to_x: f32, to_y: f32, dimension: Dimension) -> bool {
+        let distance = ((to_x - from_x).powi(2) + (to_y - from_y).powi(2)).sqrt();
+        let steps = (distance * 4.).ceil() as i32;
+        (1..steps).any(|step| {
+            let t = step as f32 / steps as f32;
+            let (x, y) = cell_at(from_x + (to_x - from_x) * t, from_y + (to_y - from_y) * t);
+            self.is_solid(x, y, dimension) || self.block_at(x, y, dimension).is_some()
+        })
+    }
+
+    // Index of the block on a cell of this dimension
+    pub(crate) fn block_at(&self, x: i32, y: i32, dimension: Dimension) -> Option<usize> {
+        self.blocks.iter().position(|block| block.x == x && block.y == y && block.dimension.contains(dimension))
+    }
+
+    /*
+        Move a block one cell if the cell behind it is empty in every dimension the block is in
+        Only plates can be covered, the block has to be pushed off them again
+        Moving walls and sentries overlapping the cell block it too
+    */
+    pub(crate) fn push_block(&mut self, index: usize, dx: i32, dy: i32) -> bool {
+        let block = self.blocks[index];
+        let (x, y) = (block.x + dx, block.y + dy);
+        let free = block.dimension.dimensions().all(|dimension| {
+            let floor = match self.at(x, y, dimension).map(|cell| cell.item_type) {
+                Some(ItemType::None) => true,
+                Some(ItemType::Switch(switch)) => switch.kind == SwitchKind::Plate,
+                _ => false,
+            };
+            let overlaps = |other_x: f32, other_y: f32| (other_x - x as f32).abs() < 1. && (other_y - y as f32).abs() < 1.;
+            let moving_wall = self.moving_walls.iter().any(|w| w.path.dimension.contains(dimension) && overlaps(w.x, w.y));
+            let sentry = self.sentries.iter().any(|s| s.data.dimension == dimension && overlaps(s.x, s.y));
+            floor && self.block_at(x, y, dimension).is_none() && !moving_wall && !sentry
+        });
+        if free {
+            (self.blocks[index].x, self.blocks[index].y) = (x, y);
+        }
+        free
+    }
+
+    // Walls, closed doors, gates and one-way doors, the outside of the map is solid too
+    fn is_solid(&self, x: i32, y: i32, dimension: Dimension) -> bool {
+        match self.at(x, y, dimension) {
+            Some(cell) => match &cell.item_type {
+                ItemType::Wall => true,
+                ItemType::Door(door) => !door.open,
+                ItemType::Gate(gate) => !self.is_gate_open(gate),
+                ItemType::OneWay(one_way) => one_way.closed,
+                _ => false,
             },
+            None => true,
+        }
+    }
+
+    // Whether a body the size of the player would touch a solid cell
+    pub(crate) fn is_blocked(&self, x: f32, y: f32, dimension: Dimension) -> bool {
+        let (cell_x, cell_y) = cell_at(x, y);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let solid = self.is_solid(cell_x + dx, cell_y + dy, dimension);
+                if solid && check_wall_collision(x, y, (cell_x + dx) as f32, (cell_y + dy) as f32) {
+                    return true;
+                }
+            }
         }
+        self.blocks.iter()
+            .filter(|block| block.dimension.contains(dimension))
+            .any(|block| check_wall_collision(x, y, block.x as f32, block.y as f32))
     }
 
-    pub(crate) fn open_door(
-        &mut self,
-        commands: &mut Commands, 
-        door_id: u32,  
-        query: &Query<(Entity, &DoorId)>
-    ) {
-        for cell in &mut self.light_cells {
+    /*
+        A one-way door closes once the player left it on the far side
+        Like switches the state is shared by every dimension the tile is in
+    */
+    pub(crate) fn update_one_ways(&mut self, touched: &[(f32, f32)], player_x: f32, player_y: f32) {
+        for cell in self.cells.iter_mut().flatten() {
+            if let ItemType::OneWay(one_way) = &mut cell.item_type {
+                let crossing = touched.contains(&(cell.x, cell.y));
+                let [dx, dy] = one_way.direction.vector();
+                let past = (player_x - cell.x) * dx + (player_y - cell.y) * dy > 0.;
+                if one_way.kind == OneWayKind::Door && one_way.crossing && !crossing && past {
+                    one_way.closed = true;
+                }
+                one_way.crossing = crossing;
+            }
+        }
+    }
+
+    // Cell under a position, positions are floats but every lookup goes through here
+    pub(crate) fn cell_under(&self, x: f32, y: f32, dimension: Dimension) -> Option<Cell> {
+        let (cell_x, cell_y) = cell_at(x, y);
+        self.at(cell_x, cell_y, dimension)
+    }
+
+    pub(crate) fn terrain_at(&self, x: f32, y: f32, dimension: Dimension) -> Option<TerrainKind> {
+        self.cell_under(x, y, dimension).and_then(|cell| cell.terrain)
+    }
+
+    pub(crate) fn is_one_way_closed(&self, x: f32, y: f32, dimension: Dimension) -> bool {
+        match self.cell_under(x, y, dimension).map(|cell| cell.item_type) {
+            Some(ItemType::OneWay(one_way)) => one_way.closed,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_gate_open(&self, gate: &Gate) -> bool {
+        self.active_groups.contains(&gate.group) != gate.open_by_default
+    }
+
+    /*
+        Update every switch from the positions the player touches this frame and the blocks standing on them
+        A switch in several dimensions shares its state so switching dimension on it does nothing
+    */
+    pub(crate) fn update_switches(&mut self, touched: &[(f32, f32)]) {
+        let weighed: Vec<(f32, f32)> = self.blocks.iter()
+            .filter(|block| block.dimension.dimensions().any(|dimension| {
+                matches!(self.at(block.x, block.y, dimension).map(|cell| cell.item_type), Some(ItemType::Switch(_)))
+            }))
+            .map(|block| (block.x as f32, block.y as f32))
+            .collect();
+        for cell in self.cells.iter_mut().flatten() {
+            if let ItemType::Switch(switch) = &mut cell.item_type {
+                let pressed = touched.contains(&(cell.x, cell.y)) || weighed.contains(&(cell.x, cell.y));
+                match switch.kind {
+                    SwitchKind::Lever => if pressed && !switch.pressed {
+                        switch.on = !switch.on;
+                    },
+                    SwitchKind::Plate => switch.on = pressed,
+                }
+                switch.pressed = pressed;
+            }
+        }
+
+        self.active_groups = self.cells.iter().flatten()
+            .filter_map(|cell| match &cell.item_type {
+                ItemType::Switch(switch) if switch.on => Some(switch.group),
+                _ => None,
+            })
+            .collect();
+    }
+
+    pub(crate) fn open_door(&mut self, door_id: u32) {
+        for cell in self.cells.iter_mut().flatten() {
             if let ItemType::Door(door) = &mut cell.item_type {
                 if door.id == door_id {
                     door.open = true;
+                    door.remaining = door.timer;
                 }
             } else if let ItemType::Key(key) = &mut cell.item_type {
                 if key.door_id == door_id {
@@ -124,61 +461,352 @@ impl Map {
                 }
             }
         }
-        for cell in &mut self.dark_cells {
+    }
+
+    // Open the door at this position in every dimension it is in, with the inventory doors of the same id stay closed
+    pub(crate) fn open_door_at(&mut self, x: f32, y: f32) {
+        for cell in self.cells.iter_mut().flatten().filter(|cell| cell.x == x && cell.y == y) {
             if let ItemType::Door(door) = &mut cell.item_type {
-                if door.id == door_id {
-                    door.open = true;
+                door.open = true;
+                door.remaining = door.timer;
+            }
+        }
+    }
+
+    pub(crate) fn take_key_at(&mut self, x: f32, y: f32) {
+        for cell in self.cells.iter_mut().flatten().filter(|cell| cell.x == x && cell.y == y) {
+            if let ItemType::Key(key) = &mut cell.item_type {
+                key.taken = true;
+            }
+        }
+    }
+
+    pub(crate) fn take_collectible_at(&mut self, x: f32, y: f32) {
+        for cell in self.cells.iter_mut().flatten().filter(|cell| cell.x == x && cell.y == y) {
+            if let ItemType::Collectible(collectible) = &mut cell.item_type {
+                collectible.taken = true;
+            }
+        }
+    }
+
+    // Index of the switch zone under the player, the first one wins when they overlap
+    pub(crate) fn zone_at(&self, x: f32, y: f32) -> Option<usize> {
+        let (cell_x, cell_y) = cell_at(x, y);
+        self.zones.iter().position(|zone| zone.contains(cell_x, cell_y))
+    }
+
+    pub(crate) fn take_energy_at(&mut self, x: f32, y: f32) {
+        for cell in self.cells.iter_mut().flatten().filter(|cell| cell.x == x && cell.y == y) {
+            if let ItemType::EnergyPickup(pickup) = &mut cell.item_type {
+                pickup.taken = true;
+            }
+        }
+    }
+
+    pub(crate) fn is_energy_taken_at(&self, x: f32, y: f32, dimension: Dimension) -> bool {
+        matches!(self.cell_under(x, y, dimension).map(|cell| cell.item_type), Some(ItemType::EnergyPickup(e)) if e.taken)
+    }
+
+    // Collectibles gathered, they are stored in the cells so checkpoints bring them back too
+    pub(crate) fn collected(&self) -> usize {
+        self.collectible_positions(true).len()
+    }
+
+    // Cells holding a collectible in any dimension, the total and the collected ones count the same way
+    fn collectible_positions(&self, taken_only: bool) -> HashSet<(i32, i32)> {
+        self.cells.iter().flatten()
+            .filter(|cell| matches!(&cell.item_type, ItemType::Collectible(collectible) if collectible.taken || !taken_only))
+            .map(|cell| (cell.x as i32, cell.y as i32))
+            .collect()
+    }
+
+    pub(crate) fn is_collectible_taken_at(&self, x: f32, y: f32, dimension: Dimension) -> bool {
+        matches!(self.cell_under(x, y, dimension).map(|cell| cell.item_type), Some(ItemType::Collectible(c)) if c.taken)
+    }
+
+    /*
+        Timed doors close again once their time is out, their keys come back when no door of their id is left open
+        With the inventory the keys stay with the player, the door opens again when touched
+        Returns the doors of the given dimension that closed
+    */
+    pub(crate) fn advance_doors(&mut self, delta: f32, dimension: Dimension) -> Vec<(f32, f32)> {
+        let mut closed = Vec::new();
+        let mut closed_ids = HashSet::new();
+        for (index, cells) in self.cells.iter_mut().enumerate() {
+            for cell in cells.iter_mut() {
+                let ItemType::Door(door) = &mut cell.item_type else { continue };
+                if !door.open || door.timer <= 0. {
+                    continue;
                 }
-            } else if let ItemType::Key(key) = &mut cell.item_type {
-                if key.door_id == door_id {
-                    key.taken = true;
+                door.remaining -= delta;
+                if door.remaining <= 0. {
+                    door.open = false;
+                    closed_ids.insert(door.id);
+                    if index == dimension.index() {
+                        closed.push((cell.x, cell.y));
+                    }
                 }
             }
         }
 
-        for (entity, doorid) in query.iter() {
-            if doorid.0 == door_id {
-                commands.entity(entity).despawn();
+        for door_id in closed_ids {
+            if self.key_rule == KeyRule::Inventory || self.is_door_open(door_id) {
+                continue;
+            }
+            for cell in self.cells.iter_mut().flatten() {
+                if let ItemType::Key(key) = &mut cell.item_type {
+                    if key.door_id == door_id {
+                        key.taken = false;
+                    }
+                }
             }
         }
+        closed
+    }
+
+    // Part of the time left on an open timed door
+    pub(crate) fn door_countdown(&self, x: f32, y: f32, dimension: Dimension) -> Option<f32> {
+        match self.cell_under(x, y, dimension).map(|cell| cell.item_type) {
+            Some(ItemType::Door(door)) if door.open && door.timer > 0. => Some((door.remaining / door.timer).clamp(0., 1.)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_door_open(&self, door_id: u32) -> bool {
+        self.cells.iter().flatten().any(|cell| matches!(&cell.item_type, ItemType::Door(door) if door.id == door_id && door.open))
+    }
+
+    pub(crate) fn is_door_open_at(&self, x: f32, y: f32, dimension: Dimension) -> bool {
+        matches!(self.cell_under(x, y, dimension).map(|cell| cell.item_type), Some(ItemType::Door(door)) if door.open)
+    }
+
+    pub(crate) fn is_key_taken_at(&self, x: f32, y: f32, dimension: Dimension) -> bool {
+        matches!(self.cell_under(x, y, dimension).map(|cell| cell.item_type), Some(ItemType::Key(key)) if key.taken)
+    }
+
+    pub(crate) fn save_state(&self) -> MapState {
+        MapState {
+            cells: self.cells.clone(),
+            active_groups: self.active_groups.clone(),
+            blocks: self.blocks.clone(),
+        }
+    }
+
+    pub(crate) fn restore_state(&mut self, state: &MapState) {
+        self.cells = state.cells.clone();
+        self.active_groups = state.active_groups.clone();
+        self.blocks = state.blocks.clone();
     }
 }
 
 
+// Cells are centered on whole coordinates so a position belongs to the nearest one
+pub(crate) fn cell_at(x: f32, y: f32) -> (i32, i32) {
+    (x.round() as i32, y.round() as i32)
+}
+
 fn generate_map(map_data: &MapData, map: &mut Map) {
-    if let Some(cell) = map.get_mut_cell(map_data.goal_x, map_data.goal_y, Dimension::Light) {
-        cell.set_data(ItemType::Goal);
-    } else {
-        warn!("Parse Map Wall in invalid position: ({}, {})", map_data.goal_x, map_data.goal_y);
-    }
-    if let Some(cell) = map.get_mut_cell(map_data.goal_x, map_data.goal_y, Dimension::Dark) {
-        cell.set_data(ItemType::Goal);
-    } else {
-        warn!("Parse Map Wall in invalid position: ({}, {})", map_data.goal_x, map_data.goal_y);
+    // The goal is reachable from every dimension
+    for index in 0..map.cells.len() {
+        if let Some(cell) = map.get_mut_cell(map_data.goal_x, map_data.goal_y, Dimension(index as u8)) {
+            cell.set_data(ItemType::Goal);
+        } else {
+            warn!("Parse Map Wall in invalid position: ({}, {})", map_data.goal_x, map_data.goal_y);
+        }
     }
 
     // Iterate over the walls and add them to the corresponding cells
     for wall in &map_data.walls {
-        if let Some(cell) = map.get_mut_cell(wall.x, wall.y, wall.dimension) {
-            cell.set_data(ItemType::Wall);
-        } else {
-            warn!("Parse Map Wall in invalid position: ({}, {})", wall.x, wall.y);
+        for dimension in wall.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(wall.x, wall.y, dimension) {
+                cell.set_data(ItemType::Wall);
+            } else {
+                warn!("Parse Map Wall in invalid position: ({}, {})", wall.x, wall.y);
+            }
         }
     }
     // Iterate over the doors and add them to the corresponding cells
     for door in &map_data.doors {
-        if let Some(cell) = map.get_mut_cell(door.x, door.y, door.dimension) {
-            cell.set_data(ItemType::Door(Door { open: false, id: door.id, entity: None }));
-        } else {
-            warn!("Parse Map Door in invalid position: ({}, {})", door.x, door.y);
+        for dimension in door.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(door.x, door.y, dimension) {
+                cell.set_data(ItemType::Door(Door {
+                    open: false,
+                    id: door.id,
+                    entity: None,
+                    timer: door.timer,
+                    remaining: 0.,
+                    keys_needed: door.keys_needed,
+                    generic: door.generic,
+                    consume: door.consume,
+                }));
+            } else {
+                warn!("Parse Map Door in invalid position: ({}, {})", door.x, door.y);
+            }
         }
     }
     // Iterate over the doors and add them to the corresponding cells
     for key in &map_data.keys {
-        if let Some(cell) = map.get_mut_cell(key.x, key.y, key.dimension) {
-            cell.set_data(ItemType::Key(Key { taken: false, door_id: key.door_id, entity: None }));
-        } else {
-            warn!("Parse Map Door in invalid position: ({}, {})", key.x, key.y);
+        for dimension in key.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(key.x, key.y, dimension) {
+                cell.set_data(ItemType::Key(Key { taken: false, door_id: key.door_id, entity: None, generic: key.generic }));
+            } else {
+                warn!("Parse Map Door in invalid position: ({}, {})", key.x, key.y);
+            }
+        }
+    }
+    // Iterate over the portals and add them to the corresponding cells
+    for portal in &map_data.portals {
+        for dimension in portal.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(portal.x, portal.y, dimension) {
+                cell.set_data(ItemType::Portal(Portal {
+                    id: portal.id,
+                    exit_id: portal.exit_id,
+                    exit_dimension: portal.exit_dimension,
+                }));
+            } else {
+                warn!("Parse Map Portal in invalid position: ({}, {})", portal.x, portal.y);
+            }
+        }
+    }
+    // Iterate over the gates and add them to the corresponding cells
+    for gate in &map_data.gates {
+        for dimension in gate.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(gate.x, gate.y, dimension) {
+                cell.set_data(ItemType::Gate(Gate { group: gate.group, open_by_default: gate.open }));
+            } else {
+                warn!("Parse Map Gate in invalid position: ({}, {})", gate.x, gate.y);
+            }
+        }
+    }
+    // Iterate over the switches and add them to the corresponding cells
+    for switch in &map_data.switches {
+        for dimension in switch.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(switch.x, switch.y, dimension) {
+                cell.set_data(ItemType::Switch(Switch { group: switch.group, kind: switch.kind, on: false, pressed: false }));
+            } else {
+                warn!("Parse Map Switch in invalid position: ({}, {})", switch.x, switch.y);
+            }
+        }
+    }
+    // Iterate over the one-way tiles and add them to the corresponding cells
+    for one_way in &map_data.one_ways {
+        for dimension in one_way.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(one_way.x, one_way.y, dimension) {
+                cell.set_data(ItemType::OneWay(OneWay { direction: one_way.direction, kind: one_way.kind, crossing: false, closed: false }));
+            } else {
+                warn!("Parse Map OneWay in invalid position: ({}, {})", one_way.x, one_way.y);
+            }
+        }
+    }
+    // Iterate over the terrains and set the floor of the corresponding cells
+    for terrain in &map_data.terrains {
+        for dimension in terrain.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(terrain.x, terrain.y, dimension) {
+                cell.terrain = Some(terrain.kind);
+            } else {
+                warn!("Parse Map Terrain in invalid position: ({}, {})", terrain.x, terrain.y);
+            }
+        }
+    }
+    // Iterate over the checkpoints and add them to the corresponding cells
+    for checkpoint in &map_data.checkpoints {
+        for dimension in checkpoint.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(checkpoint.x, checkpoint.y, dimension) {
+                cell.set_data(ItemType::Checkpoint);
+            } else {
+                warn!("Parse Map Checkpoint in invalid position: ({}, {})", checkpoint.x, checkpoint.y);
+            }
         }
     }
-}
\ No newline at end of file
+    // Iterate over the collectibles and add them to the corresponding cells
+    for collectible in &map_data.collectibles {
+        for dimension in collectible.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(collectible.x, collectible.y, dimension) {
+                cell.set_data(ItemType::Collectible(Collectible { taken: false }));
+            } else {
+                warn!("Parse Map Collectible in invalid position: ({}, {})", collectible.x, collectible.y);
+            }
+        }
+    }
+    // Iterate over the energy pickups and add them to the corresponding cells
+    for pickup in &map_data.energy_pickups {
+        for dimension in pickup.dimension.dimensions() {
+            if let Some(cell) = map.get_mut_cell(pickup.x, pickup.y, dimension) {
+                cell.set_data(ItemType::EnergyPickup(EnergyPickup { amount: pickup.amount, taken: false }));
+            } else {
+                warn!("Parse Map Energy pickup in invalid position: ({}, {})", pickup.x, pickup.y);
+            }
+        }
+    }
+    // Moving walls are not cells, they only live in their dimensions mask
+    for moving_wall in &map_data.moving_walls {
+        let [x, y] = moving_wall.position_at(0.);
+        map.moving_walls.push(MovingWall { path: moving_wall.clone(), x, y });
+    }
+    for sentry in &map_data.sentries {
+        let [x, y] = path_position(&sentry.path, sentry.mode, 0.);
+        map.sentries.push(Sentry { data: sentry.clone(), x, y, travelled: 0., chasing: false });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentry_map(walls: &str) -> Map {
+        let json = format!(r#"{{"name":"sentry","size":16,"start_x":0,"start_y":0,"goal_x":15,"goal_y":8,
+            "walls":{},"doors":[],"keys":[],
+            "sentries":[{{"path":[[2,2],[2,6]],"speed":1,"dimension":"Light","chase_radius":6}}]}}"#, walls);
+        Map::new(&serde_json::from_str(&json).unwrap())
+    }
+
+    #[test]
+    fn sentry_chases_a_player_in_sight() {
+        let mut map = sentry_map("[]");
+        map.advance_sentries(SENTRY_STEP, 6., 2., Dimension::LIGHT);
+        assert!(map.sentries[0].chasing);
+    }
+
+    #[test]
+    fn sentry_does_not_see_through_walls() {
+        let mut map = sentry_map(r#"[{"x":4,"y":2,"dimension":"Light"}]"#);
+        map.advance_sentries(SENTRY_STEP, 6., 2., Dimension::LIGHT);
+        assert!(!map.sentries[0].chasing);
+    }
+
+    #[test]
+    fn duplicated_collectibles_count_once() {
+        let json = r#"{"name":"shards","size":16,"start_x":0,"start_y":0,"goal_x":15,"goal_y":8,
+            "walls":[],"doors":[],"keys":[],
+            "collectibles":[{"x":3,"y":3,"dimension":"Light"},{"x":3,"y":3,"dimension":"Light"},{"x":5,"y":3,"dimension":["Light","Dark"]}]}"#;
+        let mut map = Map::new(&serde_json::from_str(json).unwrap());
+        assert_eq!(map.collectible_total, 2);
+        for cell in map.cells.iter_mut().flatten() {
+            if let ItemType::Collectible(collectible) = &mut cell.item_type {
+                collectible.taken = true;
+            }
+        }
+        assert_eq!(map.collected(), map.collectible_total);
+    }
+
+    #[test]
+    fn zone_holds_a_player_rounded_into_it() {
+        let json = r#"{"name":"zone","size":16,"start_x":0,"start_y":0,"goal_x":15,"goal_y":8,
+            "walls":[],"doors":[],"keys":[],
+            "switch_zones":[{"x":4,"y":2,"width":2,"height":2,"kind":"Block"}]}"#;
+        let map = Map::new(&serde_json::from_str(json).unwrap());
+        assert_eq!(map.zone_at(3.6, 2.), Some(0));
+        assert_eq!(map.zone_at(3.4, 2.), None);
+    }
+
+    #[test]
+    fn block_is_not_pushed_onto_a_sentry() {
+        let json = r#"{"name":"blocks","size":16,"start_x":0,"start_y":0,"goal_x":15,"goal_y":8,
+            "walls":[],"doors":[],"keys":[],
+            "blocks":[{"x":4,"y":2,"dimension":"Light"},{"x":4,"y":5,"dimension":"Light"}],
+            "sentries":[{"path":[[5,2]],"speed":1,"dimension":"Light","chase_radius":0}]}"#;
+        let mut map = Map::new(&serde_json::from_str(json).unwrap());
+        assert!(!map.push_block(0, 1, 0));
+        assert!(map.push_block(1, 1, 0));
+    }
+}
@@ -1,6 +1,8 @@
 pub mod plugin;
+mod ability;
 mod dimension;
 mod tutorial;
+mod hud;
 mod systems;
 mod shader;
 mod physic;
@@ -1,19 +1,32 @@
-use super::{engine::GameData, systems::DoorId};
-use crate::{plugins::{game::map::{Cell, ItemType}, types::GameState}};
-use bevy::prelude::{NextState, ResMut, Commands, Query, Entity};
+use super::{
+    dimension::{switch_dimension, DimensionHandle},
+    engine::{GameData, Player, SizeDate},
+    plugin::{DeathReason, LevelResult},
+    shader::DimensionMaterial,
+    systems::{BlockId, CollectibleItem, DoorCountdown, DoorWall, EnergyItem, HazardItem, FullScreen, GateWall, KeyItem, MovingWallId, OneWayDoorWall, SentryId},
+    tutorial::Tutorial,
+};
+use crate::{plugins::{game::map::{cell_at, Cell, HazardState, ItemType}, types::GameState}};
+use map_shared::{KeyRule, TutorialEvent, ZoneKind};
+use bevy::{prelude::{warn, NextState, Res, ResMut, Query, Handle, Camera2d, Transform, Visibility, With, Without}, time::Time};
 
+#[allow(clippy::too_many_arguments)]
 pub fn physic_system(
-    mut commands: Commands, 
+    time: Res<Time>,
+    tutorial: Res<Tutorial>,
     mut game_data: ResMut<GameData>,
     mut state: ResMut<NextState<GameState>>,    
-    query: Query<(Entity, &DoorId)>
+    mut death_reason: ResMut<DeathReason>,
+    mut level_result: ResMut<LevelResult>,
+    dimension: Res<DimensionHandle>,
+    mut texture_query: Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
+    mut camera_query: Query<&mut Camera2d, With<FullScreen>>,
 ) {
     /*
         BASIC PHYSIC SYSTEM
         WE ONLY CHECK 8 BOX AROUND AND GROUND
     */
-    let x = game_data.player.x as i32;
-    let y = game_data.player.y as i32;
+    let (x, y) = cell_at(game_data.player.x, game_data.player.y);
     let mut surrounding_cells = Vec::new();
 
     for dx in -1..=1 {
@@ -26,39 +39,492 @@ pub fn physic_system(
                         x: (x + dx) as f32,
                         y: (y + dy) as f32,
                         item_type: ItemType::Wall,
+                        terrain: None,
                     });
                 }
             }
         }
     }
 
+    let mut death = None;
+    // Timed doors run with the other moving parts, a door closing on the player crushes it
+    if tutorial.current_message_index.is_none() {
+        let current = game_data.dimension;
+        let closed = game_data.map.advance_doors(time.delta_seconds(), current);
+        if closed.iter().any(|(x, y)| check_wall_collision(game_data.player.x, game_data.player.y, *x, *y)) {
+            death = Some(DeathReason::Door);
+        }
+    }
+
+    let mut touching_portal = false;
+    let mut portal_switch = false;
+    let mut touched_switches = Vec::new();
+    let mut touched_one_ways = Vec::new();
+    let move_x = game_data.player.x - game_data.player.last_x;
+    let move_y = game_data.player.y - game_data.player.last_y;
     for cell in &mut surrounding_cells {
         match &mut cell.item_type {
             ItemType::Wall => {
-                if check_wall_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
-                    state.set(GameState::Over);
+                if check_wall_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) && hit_wall(&mut game_data, cell.x, cell.y) {
+                    death.get_or_insert(DeathReason::Wall);
                 }
             }
             ItemType::Door(door) => {
                 if door.open {
                     continue;
                 } else if check_wall_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
-                    state.set(GameState::Over);                    
+                    // With the inventory the door opens when touched holding its keys
+                    if game_data.map.key_rule == KeyRule::Inventory && game_data.inventory.unlock(door) {
+                        game_data.map.open_door_at(cell.x, cell.y);
+                    } else if hit_wall(&mut game_data, cell.x, cell.y) {
+                        death.get_or_insert(DeathReason::Wall);
+                    }
                 }
             },
             ItemType::Key(key) => {
+                if key.taken || !check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    continue;
+                }
+                game_data.events.insert(TutorialEvent::FirstKey);
+                match game_data.map.key_rule {
+                    KeyRule::Instant => game_data.map.open_door(key.door_id),
+                    KeyRule::Inventory => {
+                        game_data.map.take_key_at(cell.x, cell.y);
+                        game_data.inventory.add(key);
+                    }
+                }
+            },
+            ItemType::Collectible(collectible) => {
+                if !collectible.taken && check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    game_data.map.take_collectible_at(cell.x, cell.y);
+                }
+            },
+            ItemType::EnergyPickup(pickup) => {
+                if pickup.taken || !check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    continue;
+                }
+                game_data.map.take_energy_at(cell.x, cell.y);
+                if let Some(energy) = &mut game_data.energy {
+                    energy.refill(pickup.amount);
+                }
+            },
+            ItemType::Checkpoint => {
+                let reached = game_data.checkpoint.cell == Some((cell.x, cell.y));
+                if !reached && check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    game_data.save_checkpoint(cell.x, cell.y);
+                }
+            },
+            ItemType::Gate(gate) => {
+                if game_data.map.is_gate_open(gate) {
+                    continue;
+                } else if check_wall_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) && hit_wall(&mut game_data, cell.x, cell.y) {
+                    death.get_or_insert(DeathReason::Wall);
+                }
+            },
+            ItemType::OneWay(one_way) => {
+                if !check_wall_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    continue;
+                }
+                let [dx, dy] = one_way.direction.vector();
+                let against = move_x * dx + move_y * dy < -f32::EPSILON;
+                if one_way.closed || against {
+                    if hit_wall(&mut game_data, cell.x, cell.y) {
+                        death.get_or_insert(DeathReason::Wall);
+                    }
+                } else {
+                    touched_one_ways.push((cell.x, cell.y));
+                }
+            },
+            ItemType::Switch(_) => {
                 if check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
-                    game_data.map.open_door(&mut commands, key.door_id, &query);
+                    touched_switches.push((cell.x, cell.y));
+                }
+            },
+            ItemType::Portal(portal) => {
+                if !check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    continue;
+                }
+                touching_portal = true;
+                // Arriving on the exit portal must not send the player back
+                if game_data.player.on_portal {
+                    continue;
+                }
+                let Some((exit_x, exit_y)) = game_data.map.portal_exit(portal.exit_id) else {
+                    warn!("Portal {} leads to missing portal {}", portal.id, portal.exit_id);
+                    continue;
+                };
+                game_data.player.x = exit_x;
+                game_data.player.y = exit_y;
+                game_data.player.on_portal = true;
+                if let Some(exit_dimension) = portal.exit_dimension.filter(|exit| *exit != game_data.dimension) {
+                    // Checked on the next frame like a switch from the input, landing in a wall kills
+                    portal_switch = true;
+                    switch_dimension(
+                        &mut game_data,
+                        exit_dimension,
+                        &dimension,
+                        &mut texture_query,
+                        &mut camera_query
+                    );
                 }
+                // The other cells were gathered around the old position
+                break;
             },
             ItemType::Goal => {
                 if check_circle_collision(game_data.player.x, game_data.player.y, cell.x, cell.y) {
+                    *level_result = LevelResult {
+                        key: game_data.progress_key.clone(),
+                        collected: game_data.map.collected(),
+                        total: game_data.map.collectible_total,
+                    };
                     state.set(GameState::Win);
                 }
             },
             ItemType::None => ()
         }
     }
+    if !touching_portal {
+        game_data.player.on_portal = false;
+    }
+
+    let player_dimension = game_data.dimension;
+    let moving_walls: Vec<(f32, f32)> = game_data.map.moving_walls.iter()
+        .filter(|w| w.path.dimension.contains(player_dimension))
+        .map(|w| (w.x, w.y))
+        .collect();
+    for (wall_x, wall_y) in moving_walls {
+        if check_wall_collision(game_data.player.x, game_data.player.y, wall_x, wall_y) && hit_wall(&mut game_data, wall_x, wall_y) {
+            death.get_or_insert(DeathReason::Wall);
+        }
+    }
+    // Moving into a block pushes it away from the player along the side it was touched on, a stuck block is a wall
+    let blocks: Vec<(usize, f32, f32)> = game_data.map.blocks.iter().enumerate()
+        .filter(|(_, block)| block.dimension.contains(player_dimension))
+        .map(|(index, block)| (index, block.x as f32, block.y as f32))
+        .collect();
+    for (index, block_x, block_y) in blocks {
+        if !check_wall_collision(game_data.player.x, game_data.player.y, block_x, block_y) {
+            continue;
+        }
+        let (offset_x, offset_y) = (block_x - game_data.player.x, block_y - game_data.player.y);
+        let (dx, dy) = if offset_x.abs() >= offset_y.abs() {
+            (if offset_x > 0. { 1 } else { -1 }, 0)
+        } else {
+            (0, if offset_y > 0. { 1 } else { -1 })
+        };
+        let towards = move_x * dx as f32 + move_y * dy as f32 > f32::EPSILON;
+        let switched = game_data.dimension != game_data.previous_dimension;
+        if !switched && towards && game_data.map.push_block(index, dx, dy) {
+            continue;
+        }
+        if hit_wall(&mut game_data, block_x, block_y) {
+            death.get_or_insert(DeathReason::Wall);
+        }
+    }
+    let time = game_data.map.time;
+    for hazard in game_data.map.hazards.iter().filter(|h| h.dimension == player_dimension && h.is_active(time)) {
+        if check_circle_collision(game_data.player.x, game_data.player.y, hazard.x as f32, hazard.y as f32) {
+            death.get_or_insert(DeathReason::Hazard);
+        }
+    }
+    for sentry in game_data.map.sentries.iter().filter(|s| s.data.dimension == player_dimension) {
+        if check_circle_collision(game_data.player.x, game_data.player.y, sentry.x, sentry.y) {
+            death.get_or_insert(DeathReason::Sentry);
+        }
+    }
+    if let Some(reason) = death {
+        *death_reason = reason;
+        lose_life(&mut game_data, &mut state, &dimension, &mut texture_query, &mut camera_query);
+        return;
+    }
+    game_data.map.update_switches(&touched_switches);
+    let (player_x, player_y) = (game_data.player.x, game_data.player.y);
+    game_data.map.update_one_ways(&touched_one_ways, player_x, player_y);
+    game_data.player.last_x = game_data.player.x;
+    game_data.player.last_y = game_data.player.y;
+    if !portal_switch {
+        game_data.previous_dimension = game_data.dimension;
+    }
+}
+
+pub fn moving_wall_system(
+    time: Res<Time>,
+    size_data: Res<SizeDate>,
+    tutorial: Res<Tutorial>,
+    mut game_data: ResMut<GameData>,
+    mut query: Query<(&MovingWallId, &mut Transform)>,
+) {
+    // The player can't move while a tutorial message is shown, neither do the walls
+    if tutorial.current_message_index.is_some() {
+        return;
+    }
+    game_data.map.advance_moving_walls(time.delta_seconds());
+
+    for (id, mut transform) in query.iter_mut() {
+        if let Some(moving_wall) = game_data.map.moving_walls.get(id.0) {
+            transform.translation.x = size_data.get_world_x(moving_wall.x);
+            transform.translation.y = size_data.get_world_y(moving_wall.y);
+        }
+    }
+}
+
+// Blocks jump a whole cell when pushed and go back when a checkpoint is restored
+pub fn block_system(
+    size_data: Res<SizeDate>,
+    game_data: Res<GameData>,
+    mut query: Query<(&BlockId, &mut Transform)>,
+) {
+    for (id, mut transform) in query.iter_mut() {
+        if let Some(block) = game_data.map.blocks.get(id.0) {
+            transform.translation.x = size_data.get_world_x(block.x as f32);
+            transform.translation.y = size_data.get_world_y(block.y as f32);
+        }
+    }
+}
+
+// Hazards show a dim base when off, blink before turning on and are fully drawn while active
+pub fn hazard_system(
+    time: Res<Time>,
+    game_data: Res<GameData>,
+    mut query: Query<(&HazardItem, &mut Visibility)>,
+) {
+    let blink = (time.elapsed_seconds() * 8.).fract() < 0.5;
+    for (item, mut visibility) in query.iter_mut() {
+        let Some(hazard) = game_data.map.hazards.get(item.index) else { continue };
+        let shown = match game_data.map.hazard_state(hazard) {
+            HazardState::Off => false,
+            HazardState::Warning => blink,
+            HazardState::On => true,
+        };
+        let target = if shown { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+}
+
+pub fn sentry_system(
+    time: Res<Time>,
+    size_data: Res<SizeDate>,
+    tutorial: Res<Tutorial>,
+    mut game_data: ResMut<GameData>,
+    mut query: Query<(&SentryId, &mut Transform)>,
+) {
+    if tutorial.current_message_index.is_some() {
+        return;
+    }
+    let (player_x, player_y, dimension) = (game_data.player.x, game_data.player.y, game_data.dimension);
+    game_data.map.advance_sentries(time.delta_seconds(), player_x, player_y, dimension);
+
+    for (id, mut transform) in query.iter_mut() {
+        if let Some(sentry) = game_data.map.sentries.get(id.0) {
+            transform.translation.x = size_data.get_world_x(sentry.x);
+            transform.translation.y = size_data.get_world_y(sentry.y);
+        }
+    }
+}
+
+// Walls that open and close at runtime are hidden rather than despawned
+pub fn gate_system(
+    game_data: Res<GameData>,
+    mut query: Query<(&GateWall, &mut Visibility)>,
+    mut one_way_query: Query<(&OneWayDoorWall, &mut Visibility), Without<GateWall>>,
+) {
+    for (gate_wall, mut visibility) in query.iter_mut() {
+        let target = if game_data.map.is_gate_open(&gate_wall.0) {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+    for (door_wall, mut visibility) in one_way_query.iter_mut() {
+        let target = if game_data.map.is_one_way_closed(door_wall.x, door_wall.y, door_wall.dimension) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+}
+
+// Doors and keys come back when a checkpoint is restored or a timed door closes so they are hidden rather than despawned
+// Countdown texts over the doors, apart from the doors and keys
+type CountdownQuery<'w, 's> = Query<'w, 's, (&'static DoorCountdown, &'static mut Transform, &'static mut Visibility), (Without<DoorWall>, Without<KeyItem>)>;
+
+pub fn door_system(
+    game_data: Res<GameData>,
+    size_data: Res<SizeDate>,
+    mut door_query: Query<(&DoorWall, &mut Visibility)>,
+    mut key_query: Query<(&KeyItem, &mut Visibility), Without<DoorWall>>,
+    mut countdown_query: CountdownQuery,
+) {
+    for (door, mut visibility) in door_query.iter_mut() {
+        let target = if game_data.map.is_door_open_at(door.x, door.y, door.dimension) { Visibility::Hidden } else { Visibility::Inherited };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+    for (key, mut visibility) in key_query.iter_mut() {
+        let target = if game_data.map.is_key_taken_at(key.x, key.y, key.dimension) { Visibility::Hidden } else { Visibility::Inherited };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+    // The bar shrinks with the time left before the door closes
+    for (countdown, mut transform, mut visibility) in countdown_query.iter_mut() {
+        let left = game_data.map.door_countdown(countdown.x, countdown.y, countdown.dimension);
+        if let Some(left) = left {
+            transform.scale.x = size_data.quad_width * 0.8 * left;
+        }
+        let target = if left.is_some() { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+}
+
+pub fn collectible_system(
+    game_data: Res<GameData>,
+    mut query: Query<(&CollectibleItem, &mut Visibility)>,
+) {
+    for (collectible, mut visibility) in query.iter_mut() {
+        let target = if game_data.map.is_collectible_taken_at(collectible.x, collectible.y, collectible.dimension) {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+}
+
+// Forcing and flipping zones switch the player on their own, blocking zones only stop the switch input
+pub fn zone_system(
+    time: Res<Time>,
+    tutorial: Res<Tutorial>,
+    mut game_data: ResMut<GameData>,
+    dimension: Res<DimensionHandle>,
+    mut texture_query: Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
+    mut camera_query: Query<&mut Camera2d, With<FullScreen>>,
+) {
+    if tutorial.current_message_index.is_some() {
+        return;
+    }
+    let zone = game_data.map.zone_at(game_data.player.x, game_data.player.y);
+    if zone != game_data.zone {
+        game_data.zone = zone;
+        game_data.zone_clock = 0.;
+    }
+    let target = match game_data.zone_kind() {
+        Some(ZoneKind::Force(target)) if target != game_data.dimension => Some(target),
+        Some(ZoneKind::Flip { period }) => {
+            game_data.zone_clock += time.delta_seconds();
+            if game_data.zone_clock >= period {
+                game_data.zone_clock -= period;
+                let mut next = game_data.dimension;
+                next.switch_dimension(game_data.dimension_count());
+                Some(next)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    if let Some(target) = target {
+        switch_dimension(&mut game_data, target, &dimension, &mut texture_query, &mut camera_query);
+    }
+}
+
+pub fn energy_pickup_system(
+    game_data: Res<GameData>,
+    mut query: Query<(&EnergyItem, &mut Visibility)>,
+) {
+    for (pickup, mut visibility) in query.iter_mut() {
+        let target = if game_data.map.is_energy_taken_at(pickup.x, pickup.y, pickup.dimension) {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+}
+
+/*
+    Touching a wall kills, unless collisions are forgiving: the player then slides along it
+    Switching dimension into a wall always kills
+*/
+fn hit_wall(game_data: &mut GameData, wall_x: f32, wall_y: f32) -> bool {
+    if game_data.forgiving_collisions && game_data.dimension == game_data.previous_dimension {
+        push_out_of_wall(&mut game_data.player, wall_x, wall_y);
+        false
+    } else {
+        true
+    }
+}
+
+// The game is over with the last life, otherwise the player goes back to the last checkpoint
+fn lose_life(
+    game_data: &mut GameData,
+    state: &mut NextState<GameState>,
+    dimension: &DimensionHandle,
+    texture_query: &mut Query<&mut Handle<DimensionMaterial>, With<FullScreen>>,
+    camera_query: &mut Query<&mut Camera2d, With<FullScreen>>,
+) {
+    game_data.lives = game_data.lives.saturating_sub(1);
+    game_data.events.insert(TutorialEvent::FirstDeath);
+    if game_data.lives == 0 {
+        state.set(GameState::Over);
+        return;
+    }
+    let target = game_data.respawn();
+    switch_dimension(game_data, target, dimension, texture_query, camera_query);
+    game_data.previous_dimension = target;
+}
+
+fn push_out_of_wall(player: &mut Player, wall_x: f32, wall_y: f32) {
+    let half_size = 0.5;
+    let circle_radius = 0.3;
+
+    let closest_x = player.x.clamp(wall_x - half_size, wall_x + half_size);
+    let closest_y = player.y.clamp(wall_y - half_size, wall_y + half_size);
+    let (mut normal_x, mut normal_y) = (player.x - closest_x, player.y - closest_y);
+    let distance = (normal_x * normal_x + normal_y * normal_y).sqrt();
+
+    let push = if distance > 0. {
+        normal_x /= distance;
+        normal_y /= distance;
+        circle_radius - distance
+    } else {
+        // The center is inside the wall, leave by the closest side
+        let (offset_x, offset_y) = (player.x - wall_x, player.y - wall_y);
+        if offset_x.abs() > offset_y.abs() {
+            (normal_x, normal_y) = (offset_x.signum(), 0.);
+            half_size - offset_x.abs() + circle_radius
+        } else {
+            (normal_x, normal_y) = (0., if offset_y == 0. { 1. } else { offset_y.signum() });
+            half_size - offset_y.abs() + circle_radius
+        }
+    };
+    if push <= 0. {
+        return;
+    }
+    player.x += normal_x * push;
+    player.y += normal_y * push;
+
+    // Keep the velocity along the wall only
+    let into_wall = player.vel_x * normal_x + player.vel_y * normal_y;
+    if into_wall < 0. {
+        player.vel_x -= into_wall * normal_x;
+        player.vel_y -= into_wall * normal_y;
+    }
 }
 
 fn check_circle_collision(player_x: f32, player_y: f32, key_x: f32, key_y: f32) -> bool {
@@ -73,7 +539,7 @@ fn check_circle_collision(player_x: f32, player_y: f32, key_x: f32, key_y: f32)
     distance <= (circle_radius + circle_radius)
 }
 
-fn check_wall_collision(player_x: f32, player_y: f32, wall_x: f32, wall_y: f32) -> bool {
+pub(crate) fn check_wall_collision(player_x: f32, player_y: f32, wall_x: f32, wall_y: f32) -> bool {
     let half_size = 0.5; // half size of the square (wall), assuming unit size is 1
     let circle_radius = 0.3; // radius of the circle (player), assuming diameter is 0.7
 
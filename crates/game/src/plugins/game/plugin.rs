@@ -1,25 +1,78 @@
-use bevy::prelude::{Plugin, App, IntoSystemConfig, IntoSystemAppConfig, OnExit};
+use bevy::prelude::{Plugin, App, IntoSystemConfig, IntoSystemAppConfig, OnExit, Resource};
 use bevy::ecs::schedule::{OnEnter,OnUpdate};
 use bevy::sprite::Material2dPlugin;
-use super::physic::physic_system;
+use super::physic::{physic_system, block_system, hazard_system, collectible_system, door_system, energy_pickup_system, zone_system, gate_system, moving_wall_system, sentry_system};
 use super::shader::DimensionMaterial;
-use super::systems::{setup_game, window_resize_system, cleanup_game};
+use super::systems::{setup_game, window_resize_system, cleanup_game, light_system};
 use super::input::{setup_input, move_system};
 use super::tutorial::tuto_system;
+use super::ability::ability_system;
+use super::hud::{hud_system, sign_system};
 use crate::plugins::state::types::GameState;
 
 // Game Plugin
 pub struct GamePlugin;
 
+// Collectibles gathered in the last won run
+#[derive(Resource, Clone, Default, Debug)]
+pub struct LevelResult {
+    // Progress key of the map
+    pub key: String,
+    pub collected: usize,
+    pub total: usize,
+}
+impl LevelResult {
+    // A level without collectibles is complete once won
+    pub fn completion(&self) -> u32 {
+        (self.collected * 100).checked_div(self.total).map_or(100, |completion| completion as u32)
+    }
+}
+
+// What ended the last run, shown on the game over screen
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DeathReason {
+    #[default]
+    Wall,
+    Sentry,
+    // A timed door closed on the player
+    Door,
+    Hazard,
+}
+impl DeathReason {
+    pub fn message(&self) -> &'static str {
+        match self {
+            DeathReason::Wall => "You hit a wall",
+            DeathReason::Sentry => "A sentry caught you",
+            DeathReason::Door => "A door closed on you",
+            DeathReason::Hazard => "You walked into a trap",
+        }
+    }
+}
+
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {        
         app.add_plugin(Material2dPlugin::<DimensionMaterial>::default());
+        app.insert_resource::<DeathReason>(DeathReason::default());
+        app.insert_resource::<LevelResult>(LevelResult::default());
         app.add_startup_system(setup_input);
         app.add_system(setup_game.in_schedule(OnEnter(GameState::Game)));
+        app.add_system(ability_system.before(move_system).in_set(OnUpdate(GameState::Game)));
         app.add_system(move_system.in_set(OnUpdate(GameState::Game)));
-        app.add_system(tuto_system.in_set(OnUpdate(GameState::Game)));        
+        app.add_system(tuto_system.in_set(OnUpdate(GameState::Game)));
+        app.add_system(hud_system.in_set(OnUpdate(GameState::Game)));
+        app.add_system(sign_system.in_set(OnUpdate(GameState::Game)));        
+        app.add_system(moving_wall_system.before(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(sentry_system.before(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(zone_system.after(move_system).before(physic_system).in_set(OnUpdate(GameState::Game)));
         app.add_system(physic_system.in_set(OnUpdate(GameState::Game)));
+        app.add_system(hazard_system.after(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(block_system.after(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(gate_system.after(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(door_system.after(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(collectible_system.after(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(energy_pickup_system.after(physic_system).in_set(OnUpdate(GameState::Game)));
+        app.add_system(light_system.in_set(OnUpdate(GameState::Game)));
         app.add_system(window_resize_system.in_set(OnUpdate(GameState::Game)));    
         app.add_system(cleanup_game.in_schedule(OnExit(GameState::Game)));    
     }    
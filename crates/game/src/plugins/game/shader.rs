@@ -1,5 +1,6 @@
-use bevy::{render::render_resource::{AsBindGroup, ShaderRef, ShaderType}, reflect::{TypeUuid, Reflect}, prelude::{Vec2, Handle, Image, Color}, sprite::Material2d};
+use bevy::{render::render_resource::{AsBindGroup, ShaderRef}, reflect::TypeUuid, prelude::{Handle, Image}, sprite::Material2d};
 
+pub use data::ShaderData;
 
 #[derive(AsBindGroup, TypeUuid, Debug, Clone)]
 #[uuid = "f690fdae-d598-45ab-8225-97e2a3f056e0"]
@@ -11,14 +12,27 @@ pub struct DimensionMaterial {
     pub texture: Handle<Image>,
 }
 
-#[derive(Reflect, TypeUuid, Debug, Clone, ShaderType)]
-#[uuid = "b02f8b7b-80de-40a7-8f8f-9f61138e2bf0"]
-pub struct ShaderData {
-    pub player_position: Vec2,
-    pub player_direction: Vec2,
-    pub goal_position: Vec2,
-    pub size_info: Vec2,
-    pub color: Color
+// The ShaderType derive checks every field with a function it never calls
+#[allow(dead_code)]
+mod data {
+    use bevy::{render::render_resource::ShaderType, reflect::{TypeUuid, Reflect}, prelude::{Vec2, Vec4, Color}};
+    use map_shared::MAX_LIGHTS;
+
+    #[derive(Reflect, TypeUuid, Debug, Clone, ShaderType)]
+    #[uuid = "b02f8b7b-80de-40a7-8f8f-9f61138e2bf0"]
+    pub struct ShaderData {
+        pub player_position: Vec2,
+        pub player_direction: Vec2,
+        pub goal_position: Vec2,
+        pub size_info: Vec2,
+        // Radius and strength of the sonar, no sonar when the strength is 0
+        pub sonar: Vec2,
+        pub color: Color,
+        // Position, radius and brightness of each light
+        pub lights: [Vec4; MAX_LIGHTS],
+        pub light_colors: [Vec4; MAX_LIGHTS],
+        pub light_count: u32,
+    }
 }
 
 impl Material2d for DimensionMaterial {
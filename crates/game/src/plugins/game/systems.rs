@@ -1,13 +1,15 @@
 use super::{
-    dimension::{init_dimension, init_dimension_world, DimensionHandle},
+    dimension::{init_dimension, init_dimension_world, light_uniforms, DimensionHandle},
     engine::GameData,
+    map::Gate,
     shader::DimensionMaterial,
-    tutorial::{init_tuto, Tutorial},
+    tutorial::Tutorial,
 };
 use map_shared::Dimension;
+use bevy::time::Time;
 use crate::{
     map::map_manager::MapManager,
-    plugins::{game::engine::SizeDate, menu::plugin::LevelChoice},
+    plugins::{game::engine::SizeDate, menu::plugin::{Difficulty, LevelChoice}},
 };
 use bevy::{
     ecs::system::{Commands, Res},
@@ -28,6 +30,7 @@ pub fn cleanup_game(mut commands: Commands, query: Query<Entity, With<GameEntity
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn setup_game(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
@@ -36,6 +39,7 @@ pub fn setup_game(
     mut meshes: ResMut<Assets<Mesh>>,
     windows: Query<&Window>,
     level: Res<LevelChoice>,
+    difficulty: Res<Difficulty>,
     map: Res<MapManager>,
 ) {
     let level_data = match *level {
@@ -49,12 +53,8 @@ pub fn setup_game(
         },
         LevelChoice::None => panic!("Level Selection to None while going inside GamePlugin"),
     };
-    let mut game_data = GameData::new(level_data);
-    let mut tutorial = Tutorial::new();
-    match *level {
-        LevelChoice::Tutorial => init_tuto(&mut game_data, &mut tutorial),
-        _ => (),
-    };
+    let mut game_data = GameData::new(level_data, *difficulty);
+    let tutorial = Tutorial::new(&level_data.tutorial);
 
     let window = windows.single();
     let size_data = SizeDate::new(
@@ -86,8 +86,75 @@ pub struct FullScreen;
 #[derive(Component)]
 pub struct GameEntity;
 
+// Shown while the door at this position is closed
+#[derive(Component)]
+pub struct DoorWall {
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+}
+
+// Time left on the open timed door at this position
+#[derive(Component)]
+pub struct DoorCountdown {
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+}
+
+// Shown until the key at this position is taken
+#[derive(Component)]
+pub struct KeyItem {
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+}
+
+// Shown until the collectible at this position is gathered
+#[derive(Component)]
+pub struct CollectibleItem {
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+}
+
+// Shown until the energy pickup at this position is taken
+#[derive(Component)]
+pub struct EnergyItem {
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+}
+
+// Index in Map::moving_walls
+#[derive(Component)]
+pub struct MovingWallId(pub usize);
+
+// Index in Map::blocks
 #[derive(Component)]
-pub struct DoorId(pub u32);
+pub struct BlockId(pub usize);
+
+// Active part of the hazard at this index in Map::hazards
+#[derive(Component)]
+pub struct HazardItem {
+    pub index: usize,
+}
+
+// Index in Map::sentries
+#[derive(Component)]
+pub struct SentryId(pub usize);
+
+// Shown once the one-way door at this position closed
+#[derive(Component)]
+pub struct OneWayDoorWall {
+    pub x: f32,
+    pub y: f32,
+    pub dimension: Dimension,
+}
+
+// Shown while the gate is closed
+#[derive(Component)]
+pub struct GateWall(pub Gate);
 
 pub fn init_target() -> Image {
     let size = Extent3d {
@@ -113,7 +180,7 @@ pub fn init_target() -> Image {
     };
     // fill image.data with zeroes
     image.resize(size);
-    return image;
+    image
 }
 
 pub fn init_world(
@@ -129,24 +196,17 @@ pub fn init_world(
 
     let dimension_handle = init_dimension(images, game_data, size_data, materials_shader, image);
 
-    init_dimension_world(
-        Dimension::Light,
-        &dimension_handle,
-        game_data,
-        size_data,
-        commands,
-        materials,
-        meshes,
-    );
-    init_dimension_world(
-        Dimension::Dark,
-        &dimension_handle,
-        game_data,
-        size_data,
-        commands,
-        materials,
-        meshes,
-    );
+    for index in 0..game_data.dimension_count() {
+        init_dimension_world(
+            Dimension(index as u8),
+            &dimension_handle,
+            game_data,
+            size_data,
+            commands,
+            materials,
+            meshes,
+        );
+    }
 
     spawn_full_screen_quad(commands, size_data, game_data, meshes, &dimension_handle);
 
@@ -179,7 +239,7 @@ fn spawn_full_screen_quad(
     commands
         .spawn(MaterialMesh2dBundle {
             material: shader_handle,
-            mesh: mesh,
+            mesh,
             ..Default::default()
         })
         .insert(FullScreen)
@@ -209,3 +269,20 @@ pub fn window_resize_system(
         }
     }
 }
+
+// Only flickering lights change, steady ones keep the values set at the start
+pub fn light_system(
+    time: Res<Time>,
+    game_data: Res<GameData>,
+    dimension: Res<DimensionHandle>,
+    mut materials: ResMut<Assets<DimensionMaterial>>,
+) {
+    if game_data.map.lights.iter().all(|light| light.flicker == 0.) {
+        return;
+    }
+    for index in 0..game_data.dimension_count() {
+        let (lights, _, _) = light_uniforms(&game_data.map, Dimension(index as u8), time.elapsed_seconds());
+        let material = materials.get_mut(&dimension.get_shader_handle(Dimension(index as u8))).unwrap();
+        material.shader_data.lights = lights;
+    }
+}
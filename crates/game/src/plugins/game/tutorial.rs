@@ -1,95 +1,138 @@
-use bevy::prelude::{Resource, Res};
-use bevy_egui::{EguiContexts, egui};
+use bevy::prelude::{Res, Resource};
+use bevy_egui::{egui, EguiContexts};
+use map_shared::{Direction, Skill, StepAction, StepTrigger, TutorialStep};
 
-use super::engine::GameData;
+use super::{engine::{GameData, SizeDate}, map::cell_at};
+
+// Seconds a step that doesn't pause stays on screen
+const HINT_DURATION: f32 = 4.;
 
-pub struct Message {
-    pub text: String,
-    pub col: i32,
-}
 #[derive(Resource)]
 pub struct Tutorial {
+    // Step waiting for the player to press A, the world is paused meanwhile
     pub current_message_index: Option<usize>,
-    message_list: Vec<Message>
+    steps: Vec<TutorialStep>,
+    // Steps already triggered, each one only shows once
+    triggered: Vec<bool>,
+    // Step shown without pausing and the time it has left
+    hint: Option<(usize, f32)>,
+    // Highlights and arrows of the last triggered step
+    marks: Vec<StepAction>,
 }
 impl Tutorial {
-    pub(crate) fn new() -> Tutorial {
+    pub(crate) fn new(steps: &[TutorialStep]) -> Tutorial {
         Tutorial {
             current_message_index: None,
-            message_list: Vec::new(),
+            steps: steps.to_vec(),
+            triggered: vec![false; steps.len()],
+            hint: None,
+            marks: Vec::new(),
         }
     }
 
-    pub(crate) fn get_current_message(&self) -> Option<&Message> {
-        if let Some(index) = self.current_message_index {
-            self.message_list.get(index)
-        } else {
-            None
-        }
+    pub(crate) fn get_current_message(&self) -> Option<&str> {
+        let index = self.current_message_index.or(self.hint.map(|(index, _)| index))?;
+        self.steps.get(index).map(|step| step.text.as_str())
     }
 
-    pub(crate) fn add_message(&mut self, text: String, col: i32) {
-        self.message_list.push(Message { text, col });
+    // Whether a text is on screen, paused or not
+    pub(crate) fn is_showing(&self) -> bool {
+        self.current_message_index.is_some() || self.hint.is_some()
     }
 
-    pub(crate) fn delete_message(&mut self) {
-        if let Some(index) = self.current_message_index {
-            self.message_list.remove(index);
-            self.current_message_index = None;
+    pub(crate) fn acknowledge(&mut self) {
+        self.current_message_index = None;
+    }
+
+    // A step is read once the player closed it or its time ran out
+    fn is_read(&self, index: usize) -> bool {
+        self.triggered[index] && self.current_message_index != Some(index) && self.hint.map(|(hint, _)| hint) != Some(index)
+    }
+
+    fn is_due(&self, step: &TutorialStep, game_data: &GameData) -> bool {
+        match step.trigger {
+            StepTrigger::Start => true,
+            StepTrigger::Region { x, y, width, height } => {
+                let (player_x, player_y) = cell_at(game_data.player.x, game_data.player.y);
+                player_x >= x && player_x < x + width && player_y >= y && player_y < y + height
+            }
+            StepTrigger::Event(event) => game_data.events.contains(&event),
+            StepTrigger::After(previous) => self.is_read(previous),
         }
     }
 
-    pub(crate) fn check_message(&mut self, game_data: &mut GameData) {
-        if self.message_list.is_empty() {
+    // Show the first step whose trigger happened, nothing else starts while a paused step waits
+    pub(crate) fn check_message(&mut self, game_data: &mut GameData, delta: f32) {
+        if let Some((index, left)) = self.hint {
+            self.hint = (left > delta).then_some((index, left - delta));
+        }
+        if self.current_message_index.is_some() {
             return;
         }
 
-        let col = game_data.player.x as i32;
-        if !game_data.dimension_enabled && col == 2 {
-            game_data.dimension_enabled = true;
+        let due = (0..self.steps.len()).find(|index| !self.triggered[*index] && self.is_due(&self.steps[*index], game_data));
+        let Some(index) = due else { return };
+        self.triggered[index] = true;
+        let step = &self.steps[index];
+        if step.pause {
+            self.current_message_index = Some(index);
+        } else {
+            self.hint = Some((index, HINT_DURATION));
         }
 
-        if self.current_message_index.is_none() {
-            if let Some(message_idx) = self.message_list.iter().position(|m| m.col == col) {
-                self.current_message_index = Some(message_idx);
+        self.marks.clear();
+        for action in &step.actions {
+            match *action {
+                StepAction::Enable(skill) => set_skill(game_data, skill, true),
+                StepAction::Disable(skill) => set_skill(game_data, skill, false),
+                StepAction::Highlight { .. } | StepAction::Arrow { .. } => self.marks.push(*action),
             }
         }
     }
 }
 
+fn set_skill(game_data: &mut GameData, skill: Skill, enabled: bool) {
+    match skill {
+        Skill::Switching => game_data.dimension_enabled = enabled,
+        Skill::Peek => game_data.abilities.peek.data.enabled = enabled,
+        Skill::Sonar => game_data.abilities.sonar.data.enabled = enabled,
+        Skill::Dash => game_data.abilities.dash.data.enabled = enabled,
+    }
+}
 
 pub fn tuto_system(
     tutorial: Res<Tutorial>,
+    size_data: Res<SizeDate>,
     mut contexts: EguiContexts,
 ) {
-    if tutorial.current_message_index.is_some() {
-        if let Some(message) = tutorial.get_current_message() {
-            let ctx = contexts.ctx_mut();
-    
-            egui::TopBottomPanel::bottom("tutorial_text_panel").show(ctx, |ui| {
-                ui.vertical(|ui| {
-                    ui.label(message.text.as_str());
-                });
+    let ctx = contexts.ctx_mut();
+    if let Some(message) = tutorial.get_current_message() {
+        egui::TopBottomPanel::bottom("tutorial_text_panel").show(ctx, |ui| {
+            ui.vertical(|ui| {
+                ui.label(message);
             });
+        });
+    }
+
+    // Marks are drawn over the game, the map fills the whole window
+    let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("tutorial_marks")));
+    let cell = egui::vec2(size_data.screen_w / size_data.grid_x as f32, size_data.screen_h / size_data.grid_y as f32);
+    let center = |x: i32, y: i32| egui::pos2((x as f32 + 0.5) * cell.x, (y as f32 + 0.5) * cell.y);
+    let stroke = egui::Stroke::new(4., egui::Color32::from_rgb(255, 215, 0));
+    for mark in &tutorial.marks {
+        match *mark {
+            StepAction::Highlight { x, y } => painter.circle_stroke(center(x, y), cell.x.min(cell.y) * 0.7, stroke),
+            StepAction::Arrow { x, y, from } => {
+                let [dx, dy] = match from {
+                    Direction::Up => [0., -1.],
+                    Direction::Down => [0., 1.],
+                    Direction::Left => [-1., 0.],
+                    Direction::Right => [1., 0.],
+                };
+                let offset = egui::vec2(dx * cell.x, dy * cell.y);
+                painter.arrow(center(x, y) + offset * 1.5, -offset, stroke);
+            }
+            StepAction::Enable(_) | StepAction::Disable(_) => (),
         }
     }
 }
-
-pub fn init_tuto(
-    game_data: &mut GameData,
-    tutorial: &mut Tutorial,
-) {
-    game_data.dimension_enabled = false;
-    tutorial.add_message("Welcome to The Veiled Path. Press A on Xbox GamePad or Space to Continue".to_string(), 0);
-    tutorial.add_message("Your goal is to reach the end represented by a circle like you".to_string(), 0);
-    tutorial.add_message("Control is left stick or arrow on keyboard".to_string(), 0);
-    tutorial.add_message("A wall is blocking you if you touch it you die".to_string(), 2);
-    tutorial.add_message("But you are lucky you can change dimension by pressing A or Space. Try It".to_string(), 2);
-    tutorial.add_message("Be carefull the wall are still in the other dimension.".to_string(), 3);
-    tutorial.add_message("If you switch back inside a wall you die".to_string(), 3);
-    tutorial.add_message("You can see other dimension here every thing a litle lighter is a wall in the other dimmension".to_string(), 3);
-    tutorial.add_message("Here the wall is present in both dimension you can't pass it".to_string(), 5);
-    tutorial.add_message("But maybe one of the wall is actually a closed door. Do you see the grey button in the top of you?".to_string(), 5);
-    tutorial.add_message("No door open? Are you sure you are in right dimension?".to_string(), 6);
-    tutorial.add_message("Easy Right? This was only the tutorial real map is a bit more complexe".to_string(), 8);
-}
\ No newline at end of file
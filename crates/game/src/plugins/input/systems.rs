@@ -3,6 +3,9 @@ use bevy::input::gamepad::{GamepadAxis, GamepadButton};
 use bevy::prelude::{Axis, Input, KeyCode, Res, ResMut, Gamepads};
 use super::types::{InputData, InputMap, Action};
 
+// Stick positions closer to the center than this are noise
+const STICK_DEADZONE: f32 = 0.2;
+
 pub fn handle_input_system(
     keyboard_input: Res<Input<KeyCode>>,
     axes: Res<Axis<GamepadAxis>>,
@@ -29,6 +32,9 @@ pub fn handle_input_system(
             if let Action::ButtonY = action {
                 input_data.button_y = true;
             }
+            if let Action::SelectDimension(index) = action {
+                input_data.select_dimension = Some(*index);
+            }
         } else if keyboard_input.pressed(*key) {            
             if let Action::LeftStickX(x) = action {
                 input_data.left_stick_x = *x;
@@ -41,6 +47,7 @@ pub fn handle_input_system(
 
     // Handle gamepad inputs
     if let Some(gamepad) = gamepads.iter().next() {
+        let (mut stick_x, mut stick_y) = (0., 0.);
         for (axis_type, action) in input_map.gamepad_axis_map.iter() {
             let gamepad_axis = GamepadAxis {
                 gamepad,
@@ -48,13 +55,16 @@ pub fn handle_input_system(
             };
             if let Some(val) = axes.get(gamepad_axis) {
                 if let Action::LeftStickX(x) = action {
-                    input_data.left_stick_x += *x * val;
+                    stick_x += *x * val;
                 }
                 if let Action::LeftStickY(y) = action {
-                    input_data.left_stick_y += *y * val;
+                    stick_y += *y * val;
                 }
             }
         }
+        let (stick_x, stick_y) = apply_deadzone(stick_x, stick_y);
+        input_data.left_stick_x += stick_x;
+        input_data.left_stick_y += stick_y;
 
         for (button_type, action) in input_map.gamepad_button_map.iter() {
             let gamepad_button = GamepadButton {
@@ -74,7 +84,30 @@ pub fn handle_input_system(
                 if let Action::ButtonY = action {
                     input_data.button_y = true;
                 }
+                if let Action::SelectDimension(index) = action {
+                    input_data.select_dimension = Some(*index);
+                }
             }
         }
     }
+
+    // Diagonals are not faster, keyboard and stick together never go past full speed
+    let length = (input_data.left_stick_x.powi(2) + input_data.left_stick_y.powi(2)).sqrt();
+    if length > 1. {
+        input_data.left_stick_x /= length;
+        input_data.left_stick_y /= length;
+    }
+}
+
+/*
+    Radial deadzone, the rest of the range is rescaled
+    so the stick still goes smoothly from 0 to full speed
+*/
+fn apply_deadzone(x: f32, y: f32) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length <= STICK_DEADZONE {
+        return (0., 0.);
+    }
+    let scaled = ((length - STICK_DEADZONE) / (1. - STICK_DEADZONE)).min(1.);
+    (x / length * scaled, y / length * scaled)
 }
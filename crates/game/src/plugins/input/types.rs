@@ -7,7 +7,9 @@ pub enum Action {
     ButtonX,
     ButtonY,
     LeftStickX(f32),
-    LeftStickY(f32)
+    LeftStickY(f32),
+    // Jump straight to a dimension by its index
+    SelectDimension(usize),
 }
 
 #[derive(Default, Resource)]
@@ -25,4 +27,5 @@ pub struct InputData {
     pub button_b: bool,
     pub button_x: bool,
     pub button_y: bool,
+    pub select_dimension: Option<usize>,
 }
\ No newline at end of file
@@ -14,9 +14,42 @@ pub enum LevelChoice {
     Custom,
 }
 
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Difficulty {
+    // Walls block instead of killing
+    Explorer,
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+impl Difficulty {
+    // Scales the map movement, a slower player is easier to steer
+    pub fn speed_scale(&self) -> f32 {
+        match self {
+            Difficulty::Explorer | Difficulty::Easy => 0.8,
+            Difficulty::Normal => 1.,
+            Difficulty::Hard => 1.25,
+        }
+    }
+
+    pub fn forgiving_collisions(&self) -> bool {
+        matches!(self, Difficulty::Explorer)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MapCode {
+    pub input: String,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource::<LevelChoice>(LevelChoice::None);
+        app.insert_resource::<MapCode>(MapCode::default());
+        app.insert_resource::<Difficulty>(Difficulty::default());
         app.add_system(menu_ui.in_set(OnUpdate(GameState::Menu)));
     }
-}
\ No newline at end of file
+}
@@ -1,39 +1,85 @@
 use bevy::{prelude::{EventWriter}, app::AppExit};
 use bevy_egui::{ egui::{self}, EguiContexts};
-use bevy::ecs::system::ResMut;
+use bevy::ecs::system::{Res, ResMut};
 use bevy::ecs::schedule::NextState;
 use crate::plugins::state::types::GameState;
-use crate::map::map_manager::MapManager;
-use super::plugin::LevelChoice;
+use crate::map::{map_manager::MapManager, code::encode_map, parser::{parse_map, MapSource}, progress::Progress};
+use super::plugin::{Difficulty, LevelChoice, MapCode};
 
+#[allow(clippy::too_many_arguments)]
 pub fn menu_ui(
     mut contexts: EguiContexts,
     mut app_exit_events: EventWriter<AppExit>,
-    map: ResMut<MapManager>,
+    mut map: ResMut<MapManager>,
+    mut map_code: ResMut<MapCode>,
     mut level: ResMut<LevelChoice>,
+    mut difficulty: ResMut<Difficulty>,
     mut state: ResMut<NextState<GameState>>,
+    progress: Res<Progress>,
 ) {
     let ctx = contexts.ctx_mut();
+    // Won levels show their best completion
+    let label = |name: &str, key: &str| match progress.best(key) {
+        Some(best) => format!("{} {}%", name, best),
+        None => name.to_string(),
+    };
 
     egui::CentralPanel::default().show(ctx, |ui| {
         ui.vertical_centered(|ui| {
             ui.heading("Menu");
             ui.add_space(30.);
-            if ui.add(egui::Button::new("Tuto")).clicked() {
+            ui.horizontal(|ui| {
+                for (value, name) in [(Difficulty::Explorer, "Explorer"), (Difficulty::Easy, "Easy"), (Difficulty::Normal, "Normal"), (Difficulty::Hard, "Hard")] {
+                    ui.selectable_value(&mut *difficulty, value, name);
+                }
+            });
+            ui.add_space(15.);
+            if ui.add(egui::Button::new(label("Tuto", &map.tuto_key))).clicked() {
                 *level = LevelChoice::Tutorial;
                 state.set(GameState::Game);
             }
             ui.add_space(15.);
-            if ui.add(egui::Button::new("Level 1")).clicked() {
+            if ui.add(egui::Button::new(label("Level 1", &map.level1_key))).clicked() {
                 *level = LevelChoice::Level1;
                 state.set(GameState::Game);
             }
             ui.add_space(15.);
-            if let Some(_) = map.custom_map {
-                if ui.add(egui::Button::new("Custom")).clicked() {
+            if let (Some(custom_map), Some(custom_key)) = (&map.custom_map, &map.custom_key) {
+                if ui.add(egui::Button::new(label("Custom", custom_key))).clicked() {
                     *level = LevelChoice::Custom;
                     state.set(GameState::Game);
                 }
+                ui.add_space(15.);
+                if ui.add(egui::Button::new("Copy code")).clicked() {
+                    match encode_map(custom_map) {
+                        Ok(code) => {
+                            ui.output_mut(|o| o.copied_text = code.clone());
+                            map_code.output = Some(code);
+                            map_code.error = None;
+                        }
+                        Err(e) => map_code.error = Some(format!("Failed to encode the custom map: {}", e)),
+                    }
+                }
+                // Clipboard access is not always granted (wasm), keep the code selectable
+                if let Some(code) = &map_code.output {
+                    ui.add(egui::TextEdit::singleline(&mut code.as_str()));
+                }
+            }
+            ui.add_space(15.);
+            ui.add(egui::TextEdit::singleline(&mut map_code.input).hint_text("Paste a map code"));
+            if ui.add(egui::Button::new("Import code")).clicked() {
+                match parse_map(MapSource::Code(map_code.input.clone())) {
+                    Ok(custom_map) => {
+                        map.set_custom_map(custom_map);
+                        map_code.input.clear();
+                        map_code.output = None;
+                        map_code.error = None;
+                    }
+                    Err(e) => map_code.error = Some(format!("Invalid map code: {}", e)),
+                }
+            }
+            if let Some(error) = &map_code.error {
+                ui.label(error.as_str());
             }
             ui.add_space(15.);
             if ui.add(egui::Button::new("Quit")).clicked() {
@@ -41,4 +87,4 @@ pub fn menu_ui(
             }
         });
     });
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
-use crate::plugins::types::GameState;
+use crate::plugins::{game::plugin::DeathReason, types::GameState};
 use bevy::{
     app::AppExit,
-    prelude::{EventWriter, NextState, ResMut},
+    prelude::{EventWriter, NextState, Res, ResMut},
 };
 use bevy_egui::{egui, EguiContexts};
 
@@ -9,6 +9,7 @@ pub fn over_ui(
     mut contexts: EguiContexts,
     mut app_exit_events: EventWriter<AppExit>,
     mut state: ResMut<NextState<GameState>>,
+    death_reason: Res<DeathReason>,
 ) {
     let ctx = contexts.ctx_mut();
 
@@ -16,6 +17,8 @@ pub fn over_ui(
         ui.vertical_centered(|ui| {
             ui.heading("GAME OVER");
             ui.add_space(15.);
+            ui.label(death_reason.message());
+            ui.add_space(15.);
 
             if ui.add(egui::Button::new("Try again")).clicked() {
                 state.set(GameState::Game);
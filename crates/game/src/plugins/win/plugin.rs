@@ -1,13 +1,14 @@
-use bevy::prelude::{Plugin, App, IntoSystemConfig, OnUpdate};
+use bevy::prelude::{Plugin, App, IntoSystemConfig, IntoSystemAppConfig, OnEnter, OnUpdate};
 use crate::plugins::types::GameState;
 
-use super::systems::win_ui;
+use super::systems::{record_progress, win_ui};
 
 // Menu Plugin
 pub struct WinPlugin;
 
 impl Plugin for WinPlugin {
     fn build(&self, app: &mut App) {
+        app.add_system(record_progress.in_schedule(OnEnter(GameState::Win)));
         app.add_system(win_ui.in_set(OnUpdate(GameState::Win)));
     }
 }
\ No newline at end of file
@@ -1,14 +1,27 @@
-use crate::plugins::types::GameState;
+use crate::{map::progress::Progress, plugins::{game::plugin::LevelResult, types::GameState}};
 use bevy::{
     app::AppExit,
-    prelude::{EventWriter, NextState, ResMut},
+    prelude::{warn, EventWriter, NextState, Res, ResMut},
 };
 use bevy_egui::{egui, EguiContexts};
 
+pub fn record_progress(
+    level_result: Res<LevelResult>,
+    mut progress: ResMut<Progress>,
+) {
+    if progress.record(&level_result.key, level_result.completion()) {
+        if let Err(e) = progress.save() {
+            warn!("Failed to save the progress: {}", e);
+        }
+    }
+}
+
 pub fn win_ui(
     mut contexts: EguiContexts,
     mut app_exit_events: EventWriter<AppExit>,
     mut state: ResMut<NextState<GameState>>,
+    level_result: Res<LevelResult>,
+    progress: Res<Progress>,
 ) {
     let ctx = contexts.ctx_mut();
 
@@ -16,6 +29,13 @@ pub fn win_ui(
         ui.vertical_centered(|ui| {
             ui.heading("YOU WIN THE LEVEL");
             ui.add_space(15.);
+            if level_result.total > 0 {
+                ui.label(format!("Shards: {}/{}", level_result.collected, level_result.total));
+            }
+            ui.label(format!("Completion: {}%", level_result.completion()));
+            if let Some(best) = progress.best(&level_result.key) {
+                ui.label(format!("Best: {}%", best));
+            }
 
             ui.add_space(15.);
             if ui.add(egui::Button::new("Menu")).clicked() {
@@ -1,11 +1,33 @@
-use std::str::FromStr;
+pub mod preview;
 
-use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
-#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
-pub enum Dimension {
-    Light,
-    Dark,
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// Render layer 0 is used by the full screen camera, a map can use the 31 others
+pub const MAX_DIMENSIONS: usize = 31;
+// Lights sent to the shader of one dimension
+pub const MAX_LIGHTS: usize = 16;
+
+/*
+    Index of a dimension in MapData::dimensions
+    The first two keep their historical names so "Light" and "Dark" still work in map files
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Dimension(pub u8);
+
+impl Dimension {
+    pub const LIGHT: Dimension = Dimension(0);
+    pub const DARK: Dimension = Dimension(1);
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    // Cycle to the next dimension, after the last one comes the first one
+    pub fn switch_dimension(&mut self, count: usize) {
+        self.0 = ((self.index() + 1) % count.max(1)) as u8;
+    }
 }
 
 impl FromStr for Dimension {
@@ -13,40 +35,325 @@ impl FromStr for Dimension {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "light" => Ok(Dimension::Light),
-            "dark" => Ok(Dimension::Dark),
-            _ => Err(()),
+            "light" | "Light" => Ok(Dimension::LIGHT),
+            "dark" | "Dark" => Ok(Dimension::DARK),
+            _ => s.parse::<u8>().map(Dimension).map_err(|_| ()),
         }
     }
 }
 
-impl Dimension {
-    pub fn switch_dimension(&mut self) {
-        match self {
-            Dimension::Light => *self = Dimension::Dark,
-            Dimension::Dark => *self = Dimension::Light,
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Dimension::LIGHT => write!(f, "Light"),
+            Dimension::DARK => write!(f, "Dark"),
+            Dimension(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+// Every dimension a map item lives in, one bit per dimension index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct DimensionMask(pub u32);
+
+impl DimensionMask {
+    pub fn all(count: usize) -> DimensionMask {
+        DimensionMask(((1u64 << count.min(32)) - 1) as u32)
+    }
+
+    pub fn contains(&self, dimension: Dimension) -> bool {
+        dimension.index() < 32 && self.0 & (1 << dimension.0) != 0
+    }
+
+    pub fn insert(&mut self, dimension: Dimension) {
+        if dimension.index() < 32 {
+            self.0 |= 1 << dimension.0;
+        }
+    }
+
+    pub fn dimensions(&self) -> impl Iterator<Item = Dimension> + '_ {
+        (0..32).map(Dimension).filter(|d| self.contains(*d))
+    }
+}
+
+impl From<Dimension> for DimensionMask {
+    fn from(dimension: Dimension) -> Self {
+        let mut mask = DimensionMask(0);
+        mask.insert(dimension);
+        mask
+    }
+}
+
+impl FromStr for DimensionMask {
+    type Err = ();
+
+    // "Light", "2" or "Light,Dark,2"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mask = DimensionMask(0);
+        for part in s.split(',') {
+            mask.insert(part.trim().parse()?);
+        }
+        Ok(mask)
+    }
+}
+
+impl fmt::Display for DimensionMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.dimensions().map(|d| d.to_string()).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/*
+    Map files write a dimension as "Light", "Dark" or its index
+    and a mask as a single dimension or a list of them.
+    Binary formats (map codes) store the raw numbers.
+*/
+impl Serialize for Dimension {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_u8(self.0);
+        }
+        match *self {
+            Dimension::LIGHT | Dimension::DARK => serializer.serialize_str(&self.to_string()),
+            Dimension(index) => serializer.serialize_u8(index),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Dimension {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            return u8::deserialize(deserializer).map(Dimension);
+        }
+        deserializer.deserialize_any(DimensionVisitor)
+    }
+}
+
+struct DimensionVisitor;
+impl<'de> de::Visitor<'de> for DimensionVisitor {
+    type Value = Dimension;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"Light\", \"Dark\" or a dimension index")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Dimension, E> {
+        v.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Dimension, E> {
+        u8::try_from(v).map(Dimension).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Dimension, E> {
+        u8::try_from(v).map(Dimension).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+}
+
+impl Serialize for DimensionMask {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_u32(self.0);
+        }
+        let dimensions: Vec<Dimension> = self.dimensions().collect();
+        match dimensions.as_slice() {
+            [dimension] => dimension.serialize(serializer),
+            _ => dimensions.serialize(serializer),
         }
     }
 }
 
+impl<'de> Deserialize<'de> for DimensionMask {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            return u32::deserialize(deserializer).map(DimensionMask);
+        }
+        deserializer.deserialize_any(DimensionMaskVisitor)
+    }
+}
+
+struct DimensionMaskVisitor;
+impl<'de> de::Visitor<'de> for DimensionMaskVisitor {
+    type Value = DimensionMask;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a dimension or a list of dimensions")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<DimensionMask, E> {
+        v.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<DimensionMask, E> {
+        DimensionVisitor.visit_u64(v).map(DimensionMask::from)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<DimensionMask, E> {
+        DimensionVisitor.visit_i64(v).map(DimensionMask::from)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<DimensionMask, A::Error> {
+        let mut mask = DimensionMask(0);
+        while let Some(dimension) = seq.next_element::<Dimension>()? {
+            mask.insert(dimension);
+        }
+        Ok(mask)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DimensionData {
+    pub name: String,
+    // Floor color, seen where the world is lit
+    pub background: [f32; 3],
+    // Walls, player and fog color
+    pub foreground: [f32; 3],
+    // Defaults to the dimension index + 1
+    #[serde(default)]
+    pub render_layer: Option<u8>,
+}
+
+impl DimensionData {
+    pub fn layer(&self, dimension: Dimension) -> u8 {
+        self.render_layer.unwrap_or(dimension.0.saturating_add(1))
+    }
+
+    /*
+        Palette used when a map doesn't define its dimensions
+        Light and Dark as in the original game, then tinted worlds
+    */
+    pub fn generated(index: usize) -> DimensionData {
+        const TINTS: [[f32; 3]; 6] = [
+            [0.85, 0.35, 0.25],
+            [0.25, 0.65, 0.35],
+            [0.25, 0.4, 0.85],
+            [0.8, 0.7, 0.2],
+            [0.6, 0.3, 0.75],
+            [0.2, 0.7, 0.75],
+        ];
+        match index {
+            0 => DimensionData {
+                name: "Light".to_string(),
+                background: [1., 1., 1.],
+                foreground: [0.05, 0.05, 0.05],
+                render_layer: None,
+            },
+            1 => DimensionData {
+                name: "Dark".to_string(),
+                background: [0., 0., 0.],
+                foreground: [0.95, 0.95, 0.95],
+                render_layer: None,
+            },
+            _ => {
+                let tint = TINTS[(index - 2) % TINTS.len()];
+                DimensionData {
+                    name: format!("Dimension {}", index + 1),
+                    background: tint.map(|c| c * 0.3),
+                    foreground: tint.map(|c| 0.5 + c * 0.5),
+                    render_layer: None,
+                }
+            }
+        }
+    }
+}
+
+pub fn default_dimensions() -> Vec<DimensionData> {
+    (0..2).map(DimensionData::generated).collect()
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct MapData {
     pub name: String,
+    // Saved progress is stored under it, maps without one are told apart by their content
+    #[serde(default)]
+    pub id: String,
     pub size: i32,
     pub start_x: i32,
     pub start_y: i32,
     pub goal_x: i32,
     pub goal_y: i32,
+    #[serde(default = "default_dimensions")]
+    pub dimensions: Vec<DimensionData>,
     pub walls: Vec<Wall>,
     pub doors: Vec<Door>,
     pub keys: Vec<Key>,
+    #[serde(default)]
+    pub portals: Vec<Portal>,
+    #[serde(default)]
+    pub gates: Vec<Gate>,
+    #[serde(default)]
+    pub switches: Vec<Switch>,
+    #[serde(default)]
+    pub moving_walls: Vec<MovingWall>,
+    #[serde(default)]
+    pub sentries: Vec<Sentry>,
+    #[serde(default)]
+    pub one_ways: Vec<OneWay>,
+    #[serde(default)]
+    pub terrains: Vec<Terrain>,
+    #[serde(default)]
+    pub movement: MovementData,
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+    // Deaths allowed before the game is over, the last one included
+    #[serde(default = "default_lives")]
+    pub lives: u32,
+    // Walls push the player back instead of killing it whatever the difficulty, for tutorials
+    #[serde(default)]
+    pub forgiving_collisions: bool,
+    #[serde(default)]
+    pub key_rule: KeyRule,
+    #[serde(default)]
+    pub collectibles: Vec<Collectible>,
+    #[serde(default)]
+    pub abilities: AbilitiesData,
+    // Switching is free when the map has no energy
+    #[serde(default)]
+    pub energy: Option<EnergyData>,
+    #[serde(default)]
+    pub energy_pickups: Vec<EnergyPickup>,
+    #[serde(default)]
+    pub switch_zones: Vec<SwitchZone>,
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    #[serde(default)]
+    pub blocks: Vec<Block>,
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    #[serde(default)]
+    pub signs: Vec<Sign>,
+    #[serde(default)]
+    pub tutorial: Vec<TutorialStep>,
+}
+
+// Maps written before lives existed end the run on the first death, more lives are opt-in
+pub fn default_lives() -> u32 {
+    1
+}
+
+impl MapData {
+    pub fn render_layer(&self, dimension: Dimension) -> u8 {
+        self.dimensions
+            .get(dimension.index())
+            .map_or(dimension.0.saturating_add(1), |d| d.layer(dimension))
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct Wall {
     pub x: i32,
     pub y: i32,
-    pub dimension: Dimension,
+    pub dimension: DimensionMask,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum KeyRule {
+    // Touching a key opens every door with its id
+    #[default]
+    Instant,
+    // Keys go into the inventory, a door opens when the player touches it holding the keys it needs
+    Inventory,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -54,7 +361,24 @@ pub struct Door {
     pub x: i32,
     pub y: i32,
     pub id: u32,
-    pub dimension: Dimension,
+    pub dimension: DimensionMask,
+    // Seconds the door stays open before closing again and giving its key back, 0 stays open
+    #[serde(default)]
+    pub timer: f32,
+    // The fields below only matter with the inventory key rule
+    // Keys of its id, or generic keys, held to open it
+    #[serde(default = "default_keys_needed")]
+    pub keys_needed: u32,
+    // Opened by generic keys instead of the keys of its id
+    #[serde(default)]
+    pub generic: bool,
+    // The keys are used up when it opens
+    #[serde(default)]
+    pub consume: bool,
+}
+
+pub fn default_keys_needed() -> u32 {
+    1
 }
 
 #[derive(Deserialize, Serialize)]
@@ -62,5 +386,438 @@ pub struct Key {
     pub x: i32,
     pub y: i32,
     pub door_id: u32,
+    pub dimension: DimensionMask,
+    // Fits every generic door, door_id is then ignored
+    #[serde(default)]
+    pub generic: bool,
+}
+
+/*
+    Touching a portal moves the player onto the portal whose id is exit_id
+    When exit_dimension is set the player also lands in that dimension
+*/
+#[derive(Deserialize, Serialize)]
+pub struct Portal {
+    pub x: i32,
+    pub y: i32,
+    pub id: u32,
+    pub exit_id: u32,
+    pub dimension: DimensionMask,
+    #[serde(default)]
+    pub exit_dimension: Option<Dimension>,
+}
+
+// A wall that opens while its group is active, or closes if it starts open
+#[derive(Deserialize, Serialize)]
+pub struct Gate {
+    pub x: i32,
+    pub y: i32,
+    pub group: u32,
+    pub dimension: DimensionMask,
+    #[serde(default)]
+    pub open: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchKind {
+    // Flips its group every time the player steps on it
+    Lever,
+    // Keeps its group active while the player stands on it
+    Plate,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Switch {
+    pub x: i32,
+    pub y: i32,
+    pub group: u32,
+    pub kind: SwitchKind,
+    pub dimension: DimensionMask,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PathMode {
+    // Back and forth along the waypoints
+    #[default]
+    PingPong,
+    // From the last waypoint straight back to the first one
+    Loop,
+}
+
+// A wall block following its waypoints, it starts on the first one
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MovingWall {
+    pub path: Vec<[i32; 2]>,
+    // Cells per second
+    pub speed: f32,
+    #[serde(default)]
+    pub mode: PathMode,
+    pub dimension: DimensionMask,
+}
+
+impl MovingWall {
+    // Position along the path after a given time, the same for everyone who plays the map
+    pub fn position_at(&self, time: f32) -> [f32; 2] {
+        path_position(&self.path, self.mode, self.speed * time)
+    }
+}
+
+/*
+    Enemy living in a single dimension, it kills the player on contact
+    It patrols its waypoints and chases the player who comes within chase_radius
+*/
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Sentry {
+    pub path: Vec<[i32; 2]>,
+    // Cells per second, both when patrolling and chasing
+    pub speed: f32,
+    #[serde(default)]
+    pub mode: PathMode,
+    pub dimension: Dimension,
+    // 0 never chases
+    #[serde(default)]
+    pub chase_radius: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HazardKind {
+    Spikes,
+    Laser,
+}
+
+/*
+    Tile of a single dimension that kills while active
+    Every period it turns on at phase seconds and stays on for active seconds
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct Hazard {
+    pub x: i32,
+    pub y: i32,
+    pub kind: HazardKind,
+    pub period: f32,
+    pub active: f32,
+    #[serde(default)]
+    pub phase: f32,
     pub dimension: Dimension,
-}
\ No newline at end of file
+}
+impl Hazard {
+    // Seconds since the hazard last turned on
+    pub fn cycle_time(&self, time: f32) -> f32 {
+        (time - self.phase).rem_euclid(self.period)
+    }
+
+    pub fn is_active(&self, time: f32) -> bool {
+        self.cycle_time(time) < self.active
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    // Unit vector in map coordinates, y grows downwards
+    pub fn vector(&self) -> [f32; 2] {
+        match self {
+            Direction::Up => [0., -1.],
+            Direction::Down => [0., 1.],
+            Direction::Left => [-1., 0.],
+            Direction::Right => [1., 0.],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OneWayKind {
+    // Can be crossed again and again, always in its direction
+    #[default]
+    Arrow,
+    // Closes for good once the player went through
+    Door,
+}
+
+// A tile the player can only cross in its direction, going against it is like hitting a wall
+#[derive(Deserialize, Serialize)]
+pub struct OneWay {
+    pub x: i32,
+    pub y: i32,
+    pub direction: Direction,
+    #[serde(default)]
+    pub kind: OneWayKind,
+    pub dimension: DimensionMask,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerrainKind {
+    // Keeps the player momentum, hard to steer
+    Ice,
+    // Slows the player down
+    Mud,
+    // Pushes the player in a direction
+    Conveyor(Direction),
+}
+
+// Floor under the other items, it only changes how the player moves
+#[derive(Deserialize, Serialize)]
+pub struct Terrain {
+    pub x: i32,
+    pub y: i32,
+    pub kind: TerrainKind,
+    pub dimension: DimensionMask,
+}
+
+/*
+    Touching a checkpoint saves the player position and dimension with the doors and keys
+    Losing a life brings everything back to the last saved checkpoint
+*/
+#[derive(Deserialize, Serialize)]
+pub struct Checkpoint {
+    pub x: i32,
+    pub y: i32,
+    pub dimension: DimensionMask,
+}
+
+/*
+    Pushed one cell at a time by the player, it moves in every dimension it exists in
+    Blocks press plates, stop sentries and hide the player from them
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub x: i32,
+    pub y: i32,
+    pub dimension: DimensionMask,
+}
+
+// Text shown while the player is near and looking at it
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Sign {
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+    // In cells
+    #[serde(default = "default_sign_radius")]
+    pub radius: f32,
+    pub dimension: DimensionMask,
+}
+
+pub fn default_sign_radius() -> f32 {
+    2.5
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TutorialEvent {
+    FirstKey,
+    FirstDeath,
+    FirstSwitch,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum StepTrigger {
+    // As soon as the level starts
+    Start,
+    // The player enters the rectangle of cells
+    Region { x: i32, y: i32, width: i32, height: i32 },
+    Event(TutorialEvent),
+    // Once the step at this index has been read
+    After(usize),
+}
+
+// What a step can turn on or off
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Skill {
+    Switching,
+    Peek,
+    Sonar,
+    Dash,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum StepAction {
+    Enable(Skill),
+    Disable(Skill),
+    // Circles a cell until the next step
+    Highlight { x: i32, y: i32 },
+    // Points at a cell from the given side until the next step
+    Arrow { x: i32, y: i32, from: Direction },
+}
+
+/*
+    Message of a tutorial, each step shows once when its trigger happens
+    A pausing step stops the world until the player presses A, the others show for a few seconds
+*/
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TutorialStep {
+    pub text: String,
+    pub trigger: StepTrigger,
+    #[serde(default)]
+    pub actions: Vec<StepAction>,
+    #[serde(default = "default_pause")]
+    pub pause: bool,
+}
+
+pub fn default_pause() -> bool {
+    true
+}
+
+// Optional shard, not needed to reach the goal, gathering them all completes the level
+#[derive(Deserialize, Serialize)]
+pub struct Collectible {
+    pub x: i32,
+    pub y: i32,
+    pub dimension: DimensionMask,
+}
+
+/*
+    Every dimension switch costs energy, the meter refills over time
+    Without refill the energy is a fixed budget of switches
+*/
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct EnergyData {
+    pub max: f32,
+    // Spent on each switch
+    pub cost: f32,
+    // Energy per second, 0 for a fixed budget
+    #[serde(default)]
+    pub regen: f32,
+}
+
+// Gives energy back once touched
+#[derive(Deserialize, Serialize)]
+pub struct EnergyPickup {
+    pub x: i32,
+    pub y: i32,
+    pub amount: f32,
+    pub dimension: DimensionMask,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum ZoneKind {
+    // The player can't switch inside
+    Block,
+    // Entering puts the player in this dimension and keeps it there
+    Force(Dimension),
+    // The dimension changes on its own every period seconds
+    Flip { period: f32 },
+}
+
+// Rectangle of cells with its own switching rule, the same in every dimension
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct SwitchZone {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub kind: ZoneKind,
+}
+impl SwitchZone {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+// Reveals the world around it in its own dimensions
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub struct Light {
+    pub x: i32,
+    pub y: i32,
+    // In cells
+    pub radius: f32,
+    pub color: [f32; 3],
+    // How much the light flickers, 0 is steady and 1 can go dark
+    #[serde(default)]
+    pub flicker: f32,
+    pub dimension: DimensionMask,
+}
+
+// How the player moves on a normal floor
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct MovementData {
+    // Cells per second
+    pub max_speed: f32,
+    // Cells per second squared while the stick is pushed
+    pub acceleration: f32,
+    // Cells per second squared once it is released
+    pub friction: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct AbilityData {
+    pub enabled: bool,
+    // Seconds before it can be used again
+    pub cooldown: f32,
+    // Uses allowed in a run, 0 for no limit
+    #[serde(default)]
+    pub uses: u32,
+}
+
+// Abilities on the face buttons, each map can turn them off or limit them
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct AbilitiesData {
+    // Shows the next dimension for a moment without switching
+    pub peek: AbilityData,
+    // Reveals the walls around the player
+    pub sonar: AbilityData,
+    // Short burst of speed
+    pub dash: AbilityData,
+}
+
+impl Default for AbilitiesData {
+    fn default() -> Self {
+        let ability = |cooldown| AbilityData { enabled: true, cooldown, uses: 0 };
+        AbilitiesData {
+            peek: ability(4.),
+            sonar: ability(6.),
+            dash: ability(2.),
+        }
+    }
+}
+
+impl Default for MovementData {
+    fn default() -> Self {
+        MovementData {
+            max_speed: 2.,
+            acceleration: 16.,
+            friction: 20.,
+        }
+    }
+}
+
+// Point reached after travelling a distance along waypoints
+pub fn path_position(path: &[[i32; 2]], mode: PathMode, travelled: f32) -> [f32; 2] {
+    let points: Vec<[f32; 2]> = path.iter().map(|p| [p[0] as f32, p[1] as f32]).collect();
+    let Some(first) = points.first() else { return [0., 0.] };
+    let mut segments: Vec<([f32; 2], [f32; 2])> = points.windows(2).map(|w| (w[0], w[1])).collect();
+    if mode == PathMode::Loop && points.len() > 2 {
+        segments.push((points[points.len() - 1], *first));
+    }
+    let length: f32 = segments.iter().map(|(a, b)| distance(*a, *b)).sum();
+    if length <= 0. || !travelled.is_finite() {
+        return *first;
+    }
+
+    let mut travelled = travelled.rem_euclid(match mode {
+        PathMode::PingPong => length * 2.,
+        PathMode::Loop => length,
+    });
+    if travelled > length {
+        travelled = length * 2. - travelled;
+    }
+    for (a, b) in segments {
+        let segment = distance(a, b);
+        if travelled <= segment && segment > 0. {
+            let t = travelled / segment;
+            return [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+        }
+        travelled -= segment;
+    }
+    *points.last().unwrap_or(first)
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
@@ -0,0 +1,703 @@
+use std::fmt::Write;
+
+use crate::{Dimension, DimensionMask, HazardKind, MapData, OneWayKind, PathMode, SwitchKind, TerrainKind, ZoneKind};
+
+/*
+    CPU only map previews, no GPU or window needed so it runs on CI
+    The map is first turned into a list of shapes, then written as PNG or SVG
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewLayout {
+    // One grid per dimension, in dimension order from left to right
+    SideBySide,
+    // Every dimension drawn on the same grid with a tint per dimension
+    Overlay,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PreviewOptions {
+    pub cell_size: u32,
+    pub layout: PreviewLayout,
+    pub links: bool,
+}
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        PreviewOptions {
+            cell_size: 16,
+            layout: PreviewLayout::SideBySide,
+            links: true,
+        }
+    }
+}
+
+pub fn render_png(map_data: &MapData, options: &PreviewOptions) -> Result<Vec<u8>, png::EncodingError> {
+    let scene = Scene::new(map_data, options);
+    let mut canvas = Canvas::new(scene.width, scene.height);
+    for shape in &scene.shapes {
+        canvas.draw(shape);
+    }
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut output, scene.width, scene.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&canvas.into_rgba8())?;
+    }
+    Ok(output)
+}
+
+pub fn render_svg(map_data: &MapData, options: &PreviewOptions) -> String {
+    let scene = Scene::new(map_data, options);
+    let mut svg = String::new();
+    // Writing into a String can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = scene.width,
+        h = scene.height
+    );
+    let _ = writeln!(svg, "<title>{}</title>", escape_xml(&map_data.name));
+    for shape in &scene.shapes {
+        let _ = match shape {
+            Shape::Rect { x, y, w, h, color } => writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+                x, y, w, h, color.svg_fill()
+            ),
+            Shape::Circle { x, y, r, color } => writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                x, y, r, color.svg_fill()
+            ),
+            Shape::Ring { x, y, r, width, color } => writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke-width="{}" {}/>"#,
+                x, y, r - width / 2., width, color.svg_stroke()
+            ),
+            Shape::Line { x1, y1, x2, y2, width, color } => writeln!(
+                svg,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}" stroke-linecap="round" {}/>"#,
+                x1, y1, x2, y2, width, color.svg_stroke()
+            ),
+        };
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rgba(u8, u8, u8, f32);
+impl Rgba {
+    fn svg_fill(&self) -> String {
+        format!(r#"fill="rgb({},{},{})" fill-opacity="{}""#, self.0, self.1, self.2, self.3)
+    }
+
+    fn svg_stroke(&self) -> String {
+        format!(r#"stroke="rgb({},{},{})" stroke-opacity="{}""#, self.0, self.1, self.2, self.3)
+    }
+}
+
+const OVERLAY_BACKGROUND: Rgba = Rgba(128, 128, 128, 1.);
+const OVERLAY_TINTS: [Rgba; 6] = [
+    Rgba(235, 150, 60, 0.7),
+    Rgba(60, 110, 235, 0.7),
+    Rgba(80, 200, 90, 0.7),
+    Rgba(190, 80, 220, 0.7),
+    Rgba(230, 220, 70, 0.7),
+    Rgba(70, 210, 220, 0.7),
+];
+const START_COLOR: Rgba = Rgba(70, 200, 80, 1.);
+const GOAL_COLOR: Rgba = Rgba(40, 220, 240, 1.);
+const PORTAL_COLOR: Rgba = Rgba(180, 80, 140, 1.);
+const PATH_COLOR: Rgba = Rgba(140, 155, 180, 1.);
+const SENTRY_COLOR: Rgba = Rgba(230, 60, 70, 1.);
+const ONE_WAY_COLOR: Rgba = Rgba(60, 140, 75, 1.);
+const ICE_COLOR: Rgba = Rgba(150, 210, 240, 0.6);
+const MUD_COLOR: Rgba = Rgba(120, 85, 50, 0.6);
+const CONVEYOR_COLOR: Rgba = Rgba(150, 150, 150, 0.6);
+const CHECKPOINT_COLOR: Rgba = Rgba(185, 110, 80, 1.);
+const GENERIC_KEY_COLOR: Rgba = Rgba(200, 200, 215, 1.);
+const COLLECTIBLE_COLOR: Rgba = Rgba(140, 240, 230, 1.);
+const ENERGY_COLOR: Rgba = Rgba(255, 230, 95, 1.);
+const HAZARD_COLOR: Rgba = Rgba(200, 40, 55, 1.);
+const SIGN_COLOR: Rgba = Rgba(230, 165, 115, 1.);
+const BLOCK_COLOR: Rgba = Rgba(150, 95, 60, 1.);
+const BLOCK_ZONE_COLOR: Rgba = Rgba(230, 60, 60, 0.2);
+const FLIP_ZONE_COLOR: Rgba = Rgba(150, 80, 230, 0.2);
+// One color per door id so the key to door links can be told apart
+const ID_COLORS: [Rgba; 8] = [
+    Rgba(230, 60, 60, 1.),
+    Rgba(250, 190, 40, 1.),
+    Rgba(170, 90, 230, 1.),
+    Rgba(230, 110, 190, 1.),
+    Rgba(120, 200, 60, 1.),
+    Rgba(60, 200, 190, 1.),
+    Rgba(240, 130, 50, 1.),
+    Rgba(110, 130, 250, 1.),
+];
+
+fn id_color(id: u32) -> Rgba {
+    ID_COLORS[id as usize % ID_COLORS.len()]
+}
+
+fn palette_color(color: [f32; 3], alpha: f32) -> Rgba {
+    let channel = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+    Rgba(channel(color[0]), channel(color[1]), channel(color[2]), alpha)
+}
+
+// Same tones as the game, the other dimensions are shown faded
+fn wall_colors(map_data: &MapData, mask: DimensionMask, panel: Option<Dimension>) -> Vec<Rgba> {
+    match panel {
+        Some(panel) => {
+            let foreground = map_data.dimensions.get(panel.index()).map_or([0.5; 3], |d| d.foreground);
+            if mask.contains(panel) {
+                vec![palette_color(foreground, 1.)]
+            } else {
+                vec![palette_color(foreground, 0.1)]
+            }
+        }
+        None => mask.dimensions().map(|d| OVERLAY_TINTS[d.index() % OVERLAY_TINTS.len()]).collect(),
+    }
+}
+
+enum Shape {
+    Rect { x: f32, y: f32, w: f32, h: f32, color: Rgba },
+    Circle { x: f32, y: f32, r: f32, color: Rgba },
+    Ring { x: f32, y: f32, r: f32, width: f32, color: Rgba },
+    Line { x1: f32, y1: f32, x2: f32, y2: f32, width: f32, color: Rgba },
+}
+
+struct Scene {
+    width: u32,
+    height: u32,
+    shapes: Vec<Shape>,
+}
+impl Scene {
+    fn new(map_data: &MapData, options: &PreviewOptions) -> Scene {
+        let cell = options.cell_size.max(1) as f32;
+        let grid_w = map_data.size.max(0) as f32;
+        let grid_h = ((map_data.size / 16) * 9).max(0) as f32;
+        let gap = cell;
+
+        // A panel is a grid drawn at an offset, None being the overlay
+        let panels: Vec<(Option<Dimension>, f32)> = match options.layout {
+            PreviewLayout::SideBySide => (0..map_data.dimensions.len())
+                .map(|index| (Some(Dimension(index as u8)), index as f32 * (grid_w * cell + gap)))
+                .collect(),
+            PreviewLayout::Overlay => vec![(None, 0.)],
+        };
+        let width = panels.len() as f32 * (grid_w * cell + gap) - gap;
+        let (width, height) = (width.max(0.) as u32, (grid_h * cell) as u32);
+
+        let mut shapes = Vec::new();
+        let center = |offset: f32, x: i32, y: i32| (offset + (x as f32 + 0.5) * cell, (y as f32 + 0.5) * cell);
+        let in_panel = |panel: Option<Dimension>, mask: DimensionMask| match panel {
+            Some(p) => mask.contains(p),
+            None => true,
+        };
+
+        for (panel, offset) in &panels {
+            let background = match panel {
+                Some(dimension) => map_data.dimensions.get(dimension.index()).map_or(OVERLAY_BACKGROUND, |d| palette_color(d.background, 1.)),
+                None => OVERLAY_BACKGROUND,
+            };
+            shapes.push(Shape::Rect { x: *offset, y: 0., w: grid_w * cell, h: grid_h * cell, color: background });
+
+            // Floor first, the terrains of other dimensions are not shown as they don't matter there
+            for terrain in map_data.terrains.iter().filter(|t| in_panel(*panel, t.dimension)) {
+                let color = match terrain.kind {
+                    TerrainKind::Ice => ICE_COLOR,
+                    TerrainKind::Mud => MUD_COLOR,
+                    TerrainKind::Conveyor(_) => CONVEYOR_COLOR,
+                };
+                shapes.push(Shape::Rect {
+                    x: offset + terrain.x as f32 * cell,
+                    y: terrain.y as f32 * cell,
+                    w: cell,
+                    h: cell,
+                    color,
+                });
+                if let TerrainKind::Conveyor(direction) = terrain.kind {
+                    let (x, y) = center(*offset, terrain.x, terrain.y);
+                    let [dx, dy] = direction.vector();
+                    let color = Rgba(90, 90, 90, 1.);
+                    let width = (cell * 0.1).max(1.);
+                    for side in [-1., 1.] {
+                        let (x2, y2) = (x - (dx + dy * side) * cell * 0.2, y - (dy - dx * side) * cell * 0.2);
+                        shapes.push(Shape::Line { x1: x, y1: y, x2, y2, width, color });
+                    }
+                }
+            }
+
+            // Switch zones cover every dimension, a forced zone takes the colour of its dimension
+            for zone in &map_data.switch_zones {
+                let color = match zone.kind {
+                    ZoneKind::Block => BLOCK_ZONE_COLOR,
+                    ZoneKind::Force(dimension) => map_data.dimensions.get(dimension.index()).map_or(BLOCK_ZONE_COLOR, |d| palette_color(d.foreground, 0.2)),
+                    ZoneKind::Flip { .. } => FLIP_ZONE_COLOR,
+                };
+                shapes.push(Shape::Rect {
+                    x: offset + zone.x as f32 * cell,
+                    y: zone.y as f32 * cell,
+                    w: zone.width as f32 * cell,
+                    h: zone.height as f32 * cell,
+                    color,
+                });
+            }
+
+            // Light reach, only in the dimensions it shines in
+            for light in map_data.lights.iter().filter(|l| in_panel(*panel, l.dimension)) {
+                let (x, y) = center(*offset, light.x, light.y);
+                shapes.push(Shape::Circle { x, y, r: light.radius * cell, color: palette_color(light.color, 0.15) });
+                shapes.push(Shape::Circle { x, y, r: cell * 0.15, color: palette_color(light.color, 1.) });
+            }
+
+            // Own dimension last so it is drawn over the faded ones
+            let mut walls: Vec<_> = map_data.walls.iter().collect();
+            walls.sort_by_key(|w| in_panel(*panel, w.dimension) && panel.is_some());
+            for wall in walls {
+                for color in wall_colors(map_data, wall.dimension, *panel) {
+                    shapes.push(Shape::Rect {
+                        x: offset + wall.x as f32 * cell,
+                        y: wall.y as f32 * cell,
+                        w: cell,
+                        h: cell,
+                        color,
+                    });
+                }
+            }
+
+            for door in &map_data.doors {
+                let color = if door.generic { GENERIC_KEY_COLOR } else { id_color(door.id) };
+                if in_panel(*panel, door.dimension) {
+                    for wall_color in wall_colors(map_data, door.dimension, *panel) {
+                        shapes.push(Shape::Rect {
+                            x: offset + door.x as f32 * cell,
+                            y: door.y as f32 * cell,
+                            w: cell,
+                            h: cell,
+                            color: wall_color,
+                        });
+                    }
+                    shapes.push(Shape::Rect {
+                        x: offset + (door.x as f32 + 0.2) * cell,
+                        y: (door.y as f32 + 0.2) * cell,
+                        w: cell * 0.6,
+                        h: cell * 0.6,
+                        color,
+                    });
+                    // A timed door gets a clock face
+                    if door.timer > 0. {
+                        let (x, y) = center(*offset, door.x, door.y);
+                        let hand = Rgba(255, 255, 255, 0.9);
+                        let width = (cell * 0.08).max(1.);
+                        shapes.push(Shape::Line { x1: x, y1: y, x2: x, y2: y - cell * 0.25, width, color: hand });
+                        shapes.push(Shape::Line { x1: x, y1: y, x2: x + cell * 0.18, y2: y, width, color: hand });
+                    }
+                    // One dot per key a lock needs when it takes more than one
+                    if door.keys_needed > 1 {
+                        let count = door.keys_needed.min(5);
+                        for index in 0..count {
+                            let x = offset + (door.x as f32 + (index as f32 + 0.5) / count as f32) * cell;
+                            shapes.push(Shape::Circle { x, y: (door.y as f32 + 0.9) * cell, r: cell * 0.07, color: Rgba(255, 255, 255, 0.9) });
+                        }
+                    }
+                } else {
+                    let (x, y) = center(*offset, door.x, door.y);
+                    shapes.push(Shape::Ring { x, y, r: cell * 0.45, width: cell * 0.1, color: Rgba(color.0, color.1, color.2, 0.4) });
+                }
+            }
+
+            for key in &map_data.keys {
+                let (x, y) = center(*offset, key.x, key.y);
+                let color = if key.generic { GENERIC_KEY_COLOR } else { id_color(key.door_id) };
+                let alpha = if in_panel(*panel, key.dimension) { 1. } else { 0.3 };
+                shapes.push(Shape::Circle { x, y, r: cell * 0.3, color: Rgba(color.0, color.1, color.2, alpha) });
+            }
+
+            // A closed gate is a wall crossed by the color of its group
+            for gate in &map_data.gates {
+                let in_gate_panel = in_panel(*panel, gate.dimension);
+                if in_gate_panel && !gate.open {
+                    for wall_color in wall_colors(map_data, gate.dimension, *panel) {
+                        shapes.push(Shape::Rect {
+                            x: offset + gate.x as f32 * cell,
+                            y: gate.y as f32 * cell,
+                            w: cell,
+                            h: cell,
+                            color: wall_color,
+                        });
+                    }
+                }
+                let color = id_color(gate.group);
+                let alpha = if in_gate_panel { 1. } else { 0.3 };
+                shapes.push(Shape::Rect {
+                    x: offset + (gate.x as f32 + 0.1) * cell,
+                    y: (gate.y as f32 + 0.4) * cell,
+                    w: cell * 0.8,
+                    h: cell * 0.2,
+                    color: Rgba(color.0, color.1, color.2, alpha),
+                });
+            }
+
+            for switch in &map_data.switches {
+                let color = id_color(switch.group);
+                let alpha = if in_panel(*panel, switch.dimension) { 1. } else { 0.3 };
+                let color = Rgba(color.0, color.1, color.2, alpha);
+                match switch.kind {
+                    SwitchKind::Lever => {
+                        let (x, y) = center(*offset, switch.x, switch.y);
+                        shapes.push(Shape::Line { x1: x, y1: y, x2: x + cell * 0.25, y2: y - cell * 0.3, width: cell * 0.1, color });
+                        shapes.push(Shape::Circle { x, y, r: cell * 0.2, color });
+                    }
+                    SwitchKind::Plate => shapes.push(Shape::Rect {
+                        x: offset + (switch.x as f32 + 0.15) * cell,
+                        y: (switch.y as f32 + 0.35) * cell,
+                        w: cell * 0.7,
+                        h: cell * 0.3,
+                        color,
+                    }),
+                }
+            }
+
+            // Moving walls are drawn at their start with their path
+            for moving_wall in &map_data.moving_walls {
+                let alpha = if in_panel(*panel, moving_wall.dimension) { 0.8 } else { 0.25 };
+                let color = Rgba(PATH_COLOR.0, PATH_COLOR.1, PATH_COLOR.2, alpha);
+                let mut path: Vec<_> = moving_wall.path.iter().map(|[x, y]| center(*offset, *x, *y)).collect();
+                if moving_wall.mode == PathMode::Loop && path.len() > 2 {
+                    path.push(path[0]);
+                }
+                for segment in path.windows(2) {
+                    let ((x1, y1), (x2, y2)) = (segment[0], segment[1]);
+                    shapes.push(Shape::Line { x1, y1, x2, y2, width: (cell * 0.15).max(1.), color });
+                }
+                for (x, y) in path.iter().skip(1) {
+                    shapes.push(Shape::Circle { x: *x, y: *y, r: cell * 0.12, color });
+                }
+                if let Some([x, y]) = moving_wall.path.first() {
+                    for wall_color in wall_colors(map_data, moving_wall.dimension, *panel) {
+                        shapes.push(Shape::Rect {
+                            x: offset + *x as f32 * cell,
+                            y: *y as f32 * cell,
+                            w: cell,
+                            h: cell,
+                            color: wall_color,
+                        });
+                    }
+                    let (x, y) = center(*offset, *x, *y);
+                    shapes.push(Shape::Ring { x, y, r: cell * 0.35, width: cell * 0.1, color });
+                }
+            }
+
+            // Hazards only show in their own dimension, drawn as when active
+            for hazard in map_data.hazards.iter().filter(|h| in_panel(*panel, DimensionMask::from(h.dimension))) {
+                let (x, y) = (offset + hazard.x as f32 * cell, hazard.y as f32 * cell);
+                let width = (cell * 0.08).max(1.);
+                match hazard.kind {
+                    HazardKind::Spikes => {
+                        for spike in 0..3 {
+                            let left = x + cell * (0.1 + spike as f32 * 0.27);
+                            let (tip, right) = (left + cell * 0.135, left + cell * 0.27);
+                            shapes.push(Shape::Line { x1: left, y1: y + cell * 0.8, x2: tip, y2: y + cell * 0.2, width, color: HAZARD_COLOR });
+                            shapes.push(Shape::Line { x1: tip, y1: y + cell * 0.2, x2: right, y2: y + cell * 0.8, width, color: HAZARD_COLOR });
+                        }
+                    }
+                    HazardKind::Laser => {
+                        let y = y + cell * 0.5;
+                        shapes.push(Shape::Line { x1: x, y1: y, x2: x + cell, y2: y, width: width * 2., color: HAZARD_COLOR });
+                        shapes.push(Shape::Circle { x: x + cell * 0.1, y, r: cell * 0.1, color: HAZARD_COLOR });
+                        shapes.push(Shape::Circle { x: x + cell * 0.9, y, r: cell * 0.1, color: HAZARD_COLOR });
+                    }
+                }
+            }
+
+            // Sentries only show in their own dimension, with their patrol and chase radius
+            for sentry in &map_data.sentries {
+                if !in_panel(*panel, DimensionMask::from(sentry.dimension)) {
+                    continue;
+                }
+                let faded = Rgba(SENTRY_COLOR.0, SENTRY_COLOR.1, SENTRY_COLOR.2, 0.5);
+                let mut path: Vec<_> = sentry.path.iter().map(|[x, y]| center(*offset, *x, *y)).collect();
+                if sentry.mode == PathMode::Loop && path.len() > 2 {
+                    path.push(path[0]);
+                }
+                for segment in path.windows(2) {
+                    let ((x1, y1), (x2, y2)) = (segment[0], segment[1]);
+                    shapes.push(Shape::Line { x1, y1, x2, y2, width: (cell * 0.1).max(1.), color: faded });
+                }
+                if let Some((x, y)) = path.first().copied() {
+                    if sentry.chase_radius > 0. {
+                        let r = sentry.chase_radius * cell;
+                        shapes.push(Shape::Ring { x, y, r, width: (cell * 0.08).max(1.), color: Rgba(faded.0, faded.1, faded.2, 0.3) });
+                    }
+                    shapes.push(Shape::Circle { x, y, r: cell * 0.35, color: SENTRY_COLOR });
+                }
+            }
+
+            // An arrow in the crossing direction, a one-way door also gets a faded wall
+            for one_way in &map_data.one_ways {
+                let in_one_way_panel = in_panel(*panel, one_way.dimension);
+                if in_one_way_panel && one_way.kind == OneWayKind::Door {
+                    for wall_color in wall_colors(map_data, one_way.dimension, *panel) {
+                        shapes.push(Shape::Rect {
+                            x: offset + one_way.x as f32 * cell,
+                            y: one_way.y as f32 * cell,
+                            w: cell,
+                            h: cell,
+                            color: Rgba(wall_color.0, wall_color.1, wall_color.2, wall_color.3 * 0.3),
+                        });
+                    }
+                }
+                let alpha = if in_one_way_panel { 1. } else { 0.3 };
+                let color = Rgba(ONE_WAY_COLOR.0, ONE_WAY_COLOR.1, ONE_WAY_COLOR.2, alpha);
+                let (x, y) = center(*offset, one_way.x, one_way.y);
+                let [dx, dy] = one_way.direction.vector();
+                let (tip_x, tip_y) = (x + dx * cell * 0.35, y + dy * cell * 0.35);
+                let width = (cell * 0.12).max(1.);
+                shapes.push(Shape::Line { x1: x - dx * cell * 0.35, y1: y - dy * cell * 0.35, x2: tip_x, y2: tip_y, width, color });
+                for side in [-1., 1.] {
+                    // Arrow head, back from the tip and to each side
+                    let (x2, y2) = (tip_x - (dx + dy * side) * cell * 0.25, tip_y - (dy - dx * side) * cell * 0.25);
+                    shapes.push(Shape::Line { x1: tip_x, y1: tip_y, x2, y2, width, color });
+                }
+            }
+
+            for portal in &map_data.portals {
+                let (x, y) = center(*offset, portal.x, portal.y);
+                let alpha = if in_panel(*panel, portal.dimension) { 1. } else { 0.3 };
+                let color = Rgba(PORTAL_COLOR.0, PORTAL_COLOR.1, PORTAL_COLOR.2, alpha);
+                shapes.push(Shape::Ring { x, y, r: cell * 0.45, width: cell * 0.2, color });
+            }
+
+            // A checkpoint is a small flag
+            for checkpoint in &map_data.checkpoints {
+                let alpha = if in_panel(*panel, checkpoint.dimension) { 1. } else { 0.3 };
+                let color = Rgba(CHECKPOINT_COLOR.0, CHECKPOINT_COLOR.1, CHECKPOINT_COLOR.2, alpha);
+                let (x, y) = (offset + checkpoint.x as f32 * cell, checkpoint.y as f32 * cell);
+                let width = (cell * 0.1).max(1.);
+                shapes.push(Shape::Line { x1: x + cell * 0.3, y1: y + cell * 0.85, x2: x + cell * 0.3, y2: y + cell * 0.15, width, color });
+                shapes.push(Shape::Rect { x: x + cell * 0.3, y: y + cell * 0.15, w: cell * 0.45, h: cell * 0.3, color });
+            }
+
+            // A block is a crate, a square with a cross
+            for block in &map_data.blocks {
+                let alpha = if in_panel(*panel, block.dimension) { 1. } else { 0.3 };
+                let color = Rgba(BLOCK_COLOR.0, BLOCK_COLOR.1, BLOCK_COLOR.2, alpha);
+                let (x, y) = (offset + block.x as f32 * cell, block.y as f32 * cell);
+                let (inset, width) = (cell * 0.1, (cell * 0.08).max(1.));
+                shapes.push(Shape::Rect { x: x + inset, y: y + inset, w: cell - inset * 2., h: cell - inset * 2., color });
+                let color = Rgba(90, 55, 35, alpha);
+                shapes.push(Shape::Line { x1: x + inset, y1: y + inset, x2: x + cell - inset, y2: y + cell - inset, width, color });
+                shapes.push(Shape::Line { x1: x + cell - inset, y1: y + inset, x2: x + inset, y2: y + cell - inset, width, color });
+            }
+
+            // A sign is a board on a post
+            for sign in &map_data.signs {
+                let alpha = if in_panel(*panel, sign.dimension) { 1. } else { 0.3 };
+                let color = Rgba(SIGN_COLOR.0, SIGN_COLOR.1, SIGN_COLOR.2, alpha);
+                let (x, y) = center(*offset, sign.x, sign.y);
+                shapes.push(Shape::Line { x1: x, y1: y, x2: x, y2: y + cell * 0.4, width: (cell * 0.1).max(1.), color });
+                shapes.push(Shape::Rect { x: x - cell * 0.35, y: y - cell * 0.35, w: cell * 0.7, h: cell * 0.4, color });
+            }
+
+            // A shard is a small cross
+            for collectible in &map_data.collectibles {
+                let alpha = if in_panel(*panel, collectible.dimension) { 1. } else { 0.3 };
+                let color = Rgba(COLLECTIBLE_COLOR.0, COLLECTIBLE_COLOR.1, COLLECTIBLE_COLOR.2, alpha);
+                let (x, y) = center(*offset, collectible.x, collectible.y);
+                let (size, width) = (cell * 0.25, (cell * 0.12).max(1.));
+                shapes.push(Shape::Line { x1: x - size, y1: y, x2: x + size, y2: y, width, color });
+                shapes.push(Shape::Line { x1: x, y1: y - size, x2: x, y2: y + size, width, color });
+            }
+
+            // Energy is a small bolt
+            for pickup in &map_data.energy_pickups {
+                let alpha = if in_panel(*panel, pickup.dimension) { 1. } else { 0.3 };
+                let color = Rgba(ENERGY_COLOR.0, ENERGY_COLOR.1, ENERGY_COLOR.2, alpha);
+                let (x, y) = center(*offset, pickup.x, pickup.y);
+                let (size, width) = (cell * 0.25, (cell * 0.1).max(1.));
+                shapes.push(Shape::Line { x1: x + size * 0.4, y1: y - size, x2: x - size * 0.4, y2: y, width, color });
+                shapes.push(Shape::Line { x1: x - size * 0.4, y1: y, x2: x + size * 0.4, y2: y, width, color });
+                shapes.push(Shape::Line { x1: x + size * 0.4, y1: y, x2: x - size * 0.4, y2: y + size, width, color });
+            }
+
+            let (x, y) = center(*offset, map_data.start_x, map_data.start_y);
+            shapes.push(Shape::Circle { x, y, r: cell * 0.3, color: START_COLOR });
+            let (x, y) = center(*offset, map_data.goal_x, map_data.goal_y);
+            shapes.push(Shape::Ring { x, y, r: cell * 0.45, width: cell * 0.15, color: GOAL_COLOR });
+            shapes.push(Shape::Circle { x, y, r: cell * 0.15, color: GOAL_COLOR });
+        }
+
+        // Links go from a key to its doors, a portal to its exit and a switch to its gates, drawn in the panel of each end
+        if options.links {
+            // An item in several dimensions is linked from the first one
+            let panel_offset = |mask: DimensionMask| {
+                panels.iter().find(|(p, _)| in_panel(*p, mask)).map_or(0., |(_, offset)| *offset)
+            };
+            // Generic keys fit too many doors to be linked
+            for key in map_data.keys.iter().filter(|k| !k.generic) {
+                for door in map_data.doors.iter().filter(|d| !d.generic && d.id == key.door_id) {
+                    let color = id_color(door.id);
+                    let (x1, y1) = center(panel_offset(key.dimension), key.x, key.y);
+                    let (x2, y2) = center(panel_offset(door.dimension), door.x, door.y);
+                    shapes.push(Shape::Line { x1, y1, x2, y2, width: (cell * 0.12).max(1.), color: Rgba(color.0, color.1, color.2, 0.8) });
+                }
+            }
+            for portal in &map_data.portals {
+                for exit in map_data.portals.iter().filter(|p| p.id == portal.exit_id) {
+                    let exit_mask = portal.exit_dimension.map_or(exit.dimension, DimensionMask::from);
+                    let (x1, y1) = center(panel_offset(portal.dimension), portal.x, portal.y);
+                    let (x2, y2) = center(panel_offset(exit_mask), exit.x, exit.y);
+                    let color = Rgba(PORTAL_COLOR.0, PORTAL_COLOR.1, PORTAL_COLOR.2, 0.6);
+                    shapes.push(Shape::Line { x1, y1, x2, y2, width: (cell * 0.08).max(1.), color });
+                }
+            }
+            for switch in &map_data.switches {
+                for gate in map_data.gates.iter().filter(|g| g.group == switch.group) {
+                    let color = id_color(gate.group);
+                    let (x1, y1) = center(panel_offset(switch.dimension), switch.x, switch.y);
+                    let (x2, y2) = center(panel_offset(gate.dimension), gate.x, gate.y);
+                    shapes.push(Shape::Line { x1, y1, x2, y2, width: (cell * 0.08).max(1.), color: Rgba(color.0, color.1, color.2, 0.6) });
+                }
+            }
+        }
+
+        Scene { width, height, shapes }
+    }
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 3]>,
+}
+impl Canvas {
+    fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![[1., 1., 1.]; (width * height) as usize],
+        }
+    }
+
+    fn draw(&mut self, shape: &Shape) {
+        match *shape {
+            Shape::Rect { x, y, w, h, color } => {
+                self.fill(x, y, x + w, y + h, color, |px, py| px >= x && px < x + w && py >= y && py < y + h)
+            }
+            Shape::Circle { x, y, r, color } => {
+                self.fill(x - r, y - r, x + r, y + r, color, |px, py| (px - x).powi(2) + (py - y).powi(2) <= r * r)
+            }
+            Shape::Ring { x, y, r, width, color } => self.fill(x - r, y - r, x + r, y + r, color, |px, py| {
+                let distance = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+                distance <= r && distance >= r - width
+            }),
+            Shape::Line { x1, y1, x2, y2, width, color } => {
+                let half = width / 2.;
+                self.fill(x1.min(x2) - half, y1.min(y2) - half, x1.max(x2) + half, y1.max(y2) + half, color, |px, py| {
+                    segment_distance(px, py, x1, y1, x2, y2) <= half
+                })
+            }
+        }
+    }
+
+    // Blend color on every pixel of the box whose center is inside the shape
+    fn fill(&mut self, min_x: f32, min_y: f32, max_x: f32, max_y: f32, color: Rgba, inside: impl Fn(f32, f32) -> bool) {
+        let start_x = min_x.floor().max(0.) as u32;
+        let start_y = min_y.floor().max(0.) as u32;
+        let end_x = (max_x.ceil().max(0.) as u32).min(self.width);
+        let end_y = (max_y.ceil().max(0.) as u32).min(self.height);
+        let source = [color.0 as f32 / 255., color.1 as f32 / 255., color.2 as f32 / 255.];
+
+        for py in start_y..end_y {
+            for px in start_x..end_x {
+                if inside(px as f32 + 0.5, py as f32 + 0.5) {
+                    let pixel = &mut self.pixels[(py * self.width + px) as usize];
+                    for c in 0..3 {
+                        pixel[c] = source[c] * color.3 + pixel[c] * (1. - color.3);
+                    }
+                }
+            }
+        }
+    }
+
+    fn into_rgba8(self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|p| [(p[0] * 255.).round() as u8, (p[1] * 255.).round() as u8, (p[2] * 255.).round() as u8, 255])
+            .collect()
+    }
+}
+
+fn segment_distance(px: f32, py: f32, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0. { 0. } else { (((px - x1) * dx + (py - y1) * dy) / length).clamp(0., 1.) };
+    ((px - x1 - t * dx).powi(2) + (py - y1 - t * dy).powi(2)).sqrt()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 32 cells wide so the height is 18 cells, a key and its door on the top rows away from the start and goal
+    fn preview_map() -> MapData {
+        let json = r#"{"name":"Keys <&> doors","size":32,"start_x":1,"start_y":1,"goal_x":30,"goal_y":17,
+            "walls":[],"doors":[{"x":10,"y":4,"id":1,"dimension":"Light"}],
+            "keys":[{"x":4,"y":4,"door_id":1,"dimension":"Light"}]}"#;
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn decode(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut reader = png::Decoder::new(bytes).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        (info.width, info.height, pixels)
+    }
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> (u8, u8, u8) {
+        let index = ((y * width + x) * 4) as usize;
+        (pixels[index], pixels[index + 1], pixels[index + 2])
+    }
+
+    #[test]
+    fn overlay_png_has_one_grid_with_the_start_and_goal() {
+        let options = PreviewOptions { cell_size: 8, layout: PreviewLayout::Overlay, links: true };
+        let (width, height, pixels) = decode(&render_png(&preview_map(), &options).unwrap());
+        assert_eq!((width, height), (32 * 8, (32 / 16 * 9) * 8));
+        assert_eq!(pixel(&pixels, width, 12, 12), (START_COLOR.0, START_COLOR.1, START_COLOR.2));
+        assert_eq!(pixel(&pixels, width, 244, 140), (GOAL_COLOR.0, GOAL_COLOR.1, GOAL_COLOR.2));
+    }
+
+    #[test]
+    fn side_by_side_png_puts_a_gap_between_the_dimensions() {
+        let options = PreviewOptions { cell_size: 8, layout: PreviewLayout::SideBySide, links: true };
+        let (width, height, pixels) = decode(&render_png(&preview_map(), &options).unwrap());
+        assert_eq!((width, height), (2 * 32 * 8 + 8, (32 / 16 * 9) * 8));
+        // The second grid starts after the first one and the gap
+        let second = 32 * 8 + 8;
+        for offset in [0, second] {
+            assert_eq!(pixel(&pixels, width, offset + 12, 12), (START_COLOR.0, START_COLOR.1, START_COLOR.2));
+            assert_eq!(pixel(&pixels, width, offset + 244, 140), (GOAL_COLOR.0, GOAL_COLOR.1, GOAL_COLOR.2));
+        }
+        assert_eq!(pixel(&pixels, width, 32 * 8 + 4, 12), (255, 255, 255));
+    }
+
+    #[test]
+    fn svg_links_the_key_to_its_door_and_escapes_the_name() {
+        for layout in [PreviewLayout::SideBySide, PreviewLayout::Overlay] {
+            let svg = render_svg(&preview_map(), &PreviewOptions { cell_size: 8, layout, links: true });
+            assert!(svg.contains(r#"<line x1="36" y1="36" x2="84" y2="36""#));
+            assert!(svg.contains("<title>Keys &lt;&amp;&gt; doors</title>"));
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use std::{env, path::PathBuf};
+
+use game::{parse_map, write_ldtk, MapFile, MapSource};
+
+// Writes a JSON map (or the tutorial when no path is given) into an LDtk project
+// cargo run --example export_ldtk -- path/to/map.json path/to/map.ldtk
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let map_source = match args.get(1) {
+        Some(path) => MapSource::FilePath(PathBuf::from(path)),
+        None => MapSource::FileContent(MapFile::Tuto),
+    };
+    let output = args.get(2).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("map.ldtk"));
+
+    let map_data = parse_map(map_source).expect("Failed to parse the map");
+    write_ldtk(&map_data, &output).expect("Failed to write the LDtk project");
+    println!("LDtk project written to {}", output.display());
+}
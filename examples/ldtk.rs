@@ -1,5 +1,4 @@
-use ldtk_rust::{Project};
-use map::MapData;
+use ldtk_rust::Project;
 
 // Loads an LDtk Project file along with any external level files
 // that it references.
@@ -23,15 +22,4 @@ fn main() {
             println!("{:?}: {}", [gv.0 / width, gv.0 % width], gv.1);
         }
     }
-
-    let mapData = MapData { 
-        name: String::new(),
-        size: 0,
-        start_x: 0,
-        start_y: 0,
-        goal_x: 0,
-        goal_y: 0,
-        walls: Vec::new(),
-        doors: Vec::new(),
-        keys: Vec::new() };
-}
\ No newline at end of file
+}
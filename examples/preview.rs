@@ -0,0 +1,26 @@
+use std::{env, fs, path::PathBuf};
+
+use game::{parse_map, MapFile, MapSource};
+use map_shared::preview::{render_png, render_svg, PreviewLayout, PreviewOptions};
+
+// Writes PNG and SVG previews of a JSON map (or the level when no path is given)
+// cargo run --example preview -- path/to/map.json path/to/output [overlay]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let map_source = match args.get(1) {
+        Some(path) => MapSource::FilePath(PathBuf::from(path)),
+        None => MapSource::FileContent(MapFile::Level),
+    };
+    let output = args.get(2).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("preview"));
+    let layout = match args.get(3).map(String::as_str) {
+        Some("overlay") => PreviewLayout::Overlay,
+        _ => PreviewLayout::SideBySide,
+    };
+    let options = PreviewOptions { layout, ..Default::default() };
+
+    let map_data = parse_map(map_source).expect("Failed to parse the map");
+    let png = render_png(&map_data, &options).expect("Failed to render the PNG preview");
+    fs::write(output.with_extension("png"), png).expect("Failed to write the PNG preview");
+    fs::write(output.with_extension("svg"), render_svg(&map_data, &options)).expect("Failed to write the SVG preview");
+    println!("Previews written to {}.png and {}.svg", output.display(), output.display());
+}
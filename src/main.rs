@@ -1,29 +1,74 @@
 use game::*;
 
-use bevy::{prelude::*, diagnostic::{LogDiagnosticsPlugin, FrameTimeDiagnosticsPlugin}};
+use bevy::diagnostic::{LogDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
 use map::parser::{parse_map, MapSource, MapFile};
 use plugins::plugin::StatePlugin;
 use map::map_manager::MapManager;
-use std::{env, path::PathBuf};
+use std::{env, error::Error, fs, path::{Path, PathBuf}};
 
 fn main() {
     // Get the command line arguments.
     let args: Vec<String> = env::args().collect();
     // Parse the map.
-    let tuto_map = parse_map(MapSource::FileContent(MapFile::Tuto)).expect("Failed to parse the tutorial map");    
-    let level_map = parse_map(MapSource::FileContent(MapFile::Level)).expect("Failed to parse the level map");   
+    let tuto_map = parse_map(MapSource::FileContent(MapFile::Tuto)).expect("Failed to parse the tutorial map");
+    let level_map = parse_map(MapSource::FileContent(MapFile::Level)).expect("Failed to parse the level map");
 
     let mut map_manager = map::map_manager::MapManager::new(tuto_map, level_map).expect("Failed to create the map manager");
 
-    if args.len() > 1 {
-        let custom_map = parse_map(MapSource::FilePath(PathBuf::from("path/to/custom_map.json"))).expect("Failed to parse the custom map");
+    if let Some(map_source) = custom_map_source(&args[1..]).expect("Invalid command line") {
+        let custom_map = parse_map(map_source).expect("Failed to parse the custom map");
         map_manager.set_custom_map(custom_map);
     }
 
     App::new()
     .insert_resource::<MapManager>(map_manager)
+    .insert_resource::<Progress>(Progress::load())
     .add_plugin(StatePlugin)
     .add_plugin(LogDiagnosticsPlugin::default())
-    .add_plugin(FrameTimeDiagnosticsPlugin::default())
+    .add_plugin(FrameTimeDiagnosticsPlugin)
     .run();
 }
+
+/*
+    the_veiled_path path/to/map.json
+    the_veiled_path --png light.png dark.png [more dimensions.png] [--legend legend.json]
+    the_veiled_path --png channels.png [--legend legend.json]
+*/
+fn custom_map_source(args: &[String]) -> Result<Option<MapSource>, Box<dyn Error>> {
+    match args.first().map(String::as_str) {
+        None => Ok(None),
+        Some("--png") => {
+            let mut images = Vec::new();
+            let mut legend = None;
+            let mut args = args[1..].iter();
+            while let Some(arg) = args.next() {
+                if arg == "--legend" {
+                    let path = args.next().ok_or("--legend needs a legend file")?;
+                    legend = Some(serde_json::from_str::<PngLegend>(&fs::read_to_string(path)?)?);
+                } else {
+                    images.push(arg);
+                }
+            }
+
+            let (layout, default_legend) = match images.as_slice() {
+                [] => return Err("--png needs at least one image".into()),
+                [image] => (PngLayout::Channels(fs::read(image)?), PngLegend::greys()),
+                images => (
+                    PngLayout::PerDimension(images.iter().map(fs::read).collect::<Result<_, _>>()?),
+                    PngLegend::colors(),
+                ),
+            };
+            let name = Path::new(images[0])
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Custom".to_string());
+
+            Ok(Some(MapSource::Png(PngSource {
+                name,
+                layout,
+                legend: legend.unwrap_or(default_legend),
+            })))
+        }
+        Some(path) => Ok(Some(MapSource::FilePath(PathBuf::from(path)))),
+    }
+}
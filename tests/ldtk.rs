@@ -0,0 +1,46 @@
+use std::{env, fs, process};
+
+use game::{parse_map, write_ldtk, MapFile, MapSource};
+use ldtk_rust::Project;
+use map_shared::Dimension;
+
+// The exported project must load like our own one, dimensions first and in order
+#[test]
+fn export_loads_back_with_dimension_layers_first() {
+    let map_data = parse_map(MapSource::FileContent(MapFile::Tuto)).unwrap();
+    // One file per run so parallel runs don't read each other's export
+    let path = env::temp_dir().join(format!("the_veiled_path_export_test_{}.ldtk", process::id()));
+    write_ldtk(&map_data, &path).unwrap();
+
+    let project = Project::new(&path);
+    fs::remove_file(&path).unwrap();
+    let entity_def = project.defs.layers.last().unwrap();
+    assert_eq!(entity_def.layer_definition_type, "Entities");
+    assert!(entity_def.int_grid_values.is_empty());
+
+    let layers = project.levels[0].layer_instances.as_ref().unwrap();
+    assert_eq!(layers.len(), map_data.dimensions.len() + 1);
+    assert_eq!(layers.last().unwrap().identifier, "Entities");
+
+    for (index, layer) in layers.iter().take(map_data.dimensions.len()).enumerate() {
+        assert_eq!(layer.identifier, format!("Dimension{}", index + 1));
+        let width = layer.c_wid as i32;
+        let mut walls: Vec<(i32, i32)> = layer
+            .int_grid_csv
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value != 0)
+            .map(|(cell, _)| (cell as i32 % width, cell as i32 / width))
+            .collect();
+        let mut expected: Vec<(i32, i32)> = map_data
+            .walls
+            .iter()
+            .filter(|wall| wall.dimension.contains(Dimension(index as u8)))
+            .map(|wall| (wall.x, wall.y))
+            .collect();
+        walls.sort();
+        expected.sort();
+        expected.dedup();
+        assert_eq!(walls, expected);
+    }
+}